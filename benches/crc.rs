@@ -0,0 +1,29 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+// The crate is a binary, so the module is compiled in directly
+#[allow(dead_code)]
+#[path = "../src/crc.rs"]
+mod crc;
+
+fn checksum(c: &mut Criterion) {
+    let frame = [
+        0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+    ];
+
+    for bits in [56, 112] {
+        let mut group = c.benchmark_group(format!("mode_s_checksum/{bits}"));
+        group.throughput(Throughput::Bytes(bits as u64 / 8));
+        group.bench_function("bitwise", |b| {
+            b.iter(|| crc::mode_s_checksum_bitwise(black_box(&frame), bits))
+        });
+        group.bench_function("bytewise", |b| {
+            b.iter(|| crc::mode_s_checksum(black_box(&frame), bits))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, checksum);
+criterion_main!(benches);
@@ -0,0 +1,90 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+#[allow(dead_code)]
+#[path = "../src/magnitude.rs"]
+mod magnitude;
+
+type Convert<'a> = Box<dyn FnMut(&[u8], &mut Vec<u16>) + 'a>;
+
+/// One read off the dongle: 128k samples, about 55 ms at 2.4 MS/s
+const SAMPLES: usize = 128 * 1024;
+
+/// The float conversion the lookup table replaced
+fn cu8_float(iq: &[u8], out: &mut Vec<u16>) {
+    out.extend(iq.chunks_exact(2).map(|c| {
+        let i = (c[0] as f32 - 127.5) / 127.5;
+        let q = (c[1] as f32 - 127.5) / 127.5;
+        ((i * i + q * q).sqrt() * magnitude::FULL_SCALE) as u16
+    }));
+}
+
+fn bench_magnitude(c: &mut Criterion) {
+    // Noise-like samples, so the table lookups are spread out like on real signals
+    let mut state = 0x2545_f491_u32;
+    let iq = (0..SAMPLES * 4)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("magnitude");
+    group.throughput(Throughput::Elements(SAMPLES as u64));
+    let mut out = Vec::with_capacity(SAMPLES);
+    let mut bench = |name, bytes_per_sample, mut convert: Convert| {
+        group.bench_function(BenchmarkId::new(name, SAMPLES), |b| {
+            b.iter(|| {
+                out.clear();
+                convert(black_box(&iq[..SAMPLES * bytes_per_sample]), &mut out);
+            })
+        });
+    };
+
+    let table = magnitude::cu8_table([0.0; 2]);
+    bench("cu8_float", 2, Box::new(cu8_float));
+    bench(
+        "cu8",
+        2,
+        Box::new(|iq, out| magnitude::cu8(iq, &table, out)),
+    );
+    bench(
+        "cs16_scalar",
+        4,
+        Box::new(|iq, out| magnitude::cs16_scalar(iq, [0.0; 2], out)),
+    );
+    bench(
+        "cs16",
+        4,
+        Box::new(|iq, out| magnitude::cs16(iq, [0.0; 2], out)),
+    );
+
+    // Including the DC offset tracking
+    let mut converter = magnitude::MagnitudeConverter::new(magnitude::IqFormat::Cu8);
+    bench(
+        "cu8_dc_removal",
+        2,
+        Box::new(move |iq, out| converter.update(iq).convert(iq, out)),
+    );
+    let mut converter = magnitude::MagnitudeConverter::new(magnitude::IqFormat::Cs16);
+    bench(
+        "cs16_dc_removal",
+        4,
+        Box::new(move |iq, out| converter.update(iq).convert(iq, out)),
+    );
+    bench(
+        "noise_floor",
+        2,
+        Box::new(|iq, out| {
+            magnitude::cu8(iq, &table, out);
+            black_box(magnitude::noise_floor(out));
+        }),
+    );
+    group.finish();
+}
+
+criterion_group!(benches, bench_magnitude);
+criterion_main!(benches);
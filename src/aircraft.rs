@@ -1,14 +1,25 @@
 use std::{
+    collections::BTreeMap,
     fmt::{Debug, Display},
-    time::Instant,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
 
 use glam::DVec2;
 use rerun::{Radius, components::GeoLineString};
+use serde::{Deserialize, Serialize};
 
-use crate::cpr;
+use crate::{
+    altitude::Altitude,
+    cpr,
+    frame::{Frame, SourceId},
+    identification::{Callsign, WakeVortexCategory},
+    timestamp::Timestamp,
+};
 
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
 pub struct Icao(u32);
 
 impl Icao {
@@ -24,70 +35,327 @@ impl Display for Icao {
     }
 }
 
+impl FromStr for Icao {
+    type Err = anyhow::Error;
+
+    /// Parse a 24-bit address written as 6 hex digits, eg. `4840D6`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        anyhow::ensure!(
+            s.len() == 6 && s.bytes().all(|b| b.is_ascii_hexdigit()),
+            "ICAO address must be 6 hex digits, got {s:?}"
+        );
+        Ok(Icao(u32::from_str_radix(s, 16)?))
+    }
+}
+
+impl TryFrom<String> for Icao {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Serialize for Icao {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl Debug for Icao {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Icao({:06X})", self.0)
     }
 }
 
+/// Where a piece of aircraft state was learned from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataSource {
+    /// Extended squitter (DF 17/18)
+    AdsB,
+    /// Comm-B reply to a ground interrogation (DF 20/21)
+    CommB,
+    /// Surveillance or ACAS reply (DF 0/4/5/16)
+    Surveillance,
+}
+
+impl Display for DataSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataSource::AdsB => write!(f, "ADS-B"),
+            DataSource::CommB => write!(f, "Comm-B"),
+            DataSource::Surveillance => write!(f, "surveillance"),
+        }
+    }
+}
+
+/// A value along with when and how it was last updated
+#[derive(Debug, Clone, Copy)]
+pub struct Tracked<T> {
+    pub value: T,
+    pub updated: Timestamp,
+    pub source: DataSource,
+}
+
+impl<T> Tracked<T> {
+    pub fn new(value: T, updated: Timestamp, source: DataSource) -> Self {
+        Tracked {
+            value,
+            updated,
+            source,
+        }
+    }
+}
+
+impl<T: Display> Display for Tracked<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} via {}", self.value, self.source)
+    }
+}
+
+/// A position fix along an aircraft's track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub lat: f64,
+    pub lon: f64,
+    /// Altitude in feet at the time of the fix, if known
+    pub altitude: Option<i32>,
+    pub time: Timestamp,
+}
+
+impl TrackPoint {
+    pub fn latlon(&self) -> (f64, f64) {
+        (self.lat, self.lon)
+    }
+}
+
+/// Number of positions in every shared chunk of a [`TrackPath`]
+const PATH_CHUNK: usize = 256;
+
+/// The position fixes of an aircraft, oldest first.
+///
+/// Full chunks are shared between clones, so copying an aircraft (eg. for the HTTP API) only copies its latest
+/// positions.
+#[derive(Debug, Clone, Default)]
+pub struct TrackPath {
+    chunks: Vec<Arc<[TrackPoint]>>,
+    tail: Vec<TrackPoint>,
+}
+
+impl TrackPath {
+    pub fn push(&mut self, point: TrackPoint) {
+        self.tail.push(point);
+        if self.tail.len() == PATH_CHUNK {
+            self.chunks.push(std::mem::take(&mut self.tail).into());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len() * PATH_CHUNK + self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn last(&self) -> Option<&TrackPoint> {
+        self.tail
+            .last()
+            .or_else(|| self.chunks.last().and_then(|chunk| chunk.last()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TrackPoint> + Clone {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .chain(&self.tail)
+    }
+}
+
+/// Number of messages received from an aircraft, by downlink format and extended squitter type code
+#[derive(Debug, Clone, Default)]
+pub struct MessageCounters {
+    pub total: u64,
+    pub by_downlink_format: [u64; 32],
+    pub by_type_code: [u64; 32],
+}
+
 #[derive(Debug, Clone)]
 pub struct Aircraft {
     pub icao: Icao,
-    pub callsign: Option<String>,
+    pub callsign: Option<Tracked<Callsign>>,
+    pub category: Option<Tracked<WakeVortexCategory>>,
+    pub squawk: Option<Tracked<u16>>,
+    pub on_ground: Option<Tracked<bool>>,
+    pub alert: Option<Tracked<bool>>,
+    /// Special position identification (ident button)
+    pub spi: Option<Tracked<bool>>,
+    /// Every callsign this aircraft has used, and when it first started using it
+    pub callsign_history: Vec<(Callsign, Timestamp)>,
 
     pub even_cprlat: u32,
     pub even_cprlon: u32,
-    pub even_cprtime: Instant,
+    pub even_cprtime: Timestamp,
     pub odd_cprlat: u32,
     pub odd_cprlon: u32,
-    pub odd_cprtime: Instant,
+    pub odd_cprtime: Timestamp,
+    /// Whether the stored CPR coordinates came from surface position messages
+    pub cpr_surface: bool,
 
-    pub altitude: Option<Altitude>,
-    pub latitude: f64,
-    pub longitude: f64,
-    pub last_pos_update: Instant,
+    pub altitude_baro: Option<Tracked<Altitude>>,
+    pub altitude_geom: Option<Tracked<Altitude>>,
+    pub position: Option<Tracked<(f64, f64)>>,
 
     pub latitude_interpolated: f64,
     pub longitude_interpolated: f64,
 
-    pub path: Vec<(f64, f64)>,
+    /// Every position fix, oldest first
+    pub path: TrackPath,
 
-    pub velocity_kts: Option<DVec2>,
+    pub velocity_kts: Option<Tracked<DVec2>>,
+    /// Vertical rate in feet per minute
+    pub vertical_rate: Option<Tracked<i32>>,
+
+    pub first_seen: Timestamp,
+    /// Time of the last message received from this aircraft, of any kind
+    pub last_seen: Timestamp,
+    pub messages: MessageCounters,
+    /// Receivers that picked up this aircraft, and when they last did
+    pub seen_by: BTreeMap<SourceId, Timestamp>,
+    /// Signal levels of the most recent frames, see [`Frame::signal_level`]
+    pub signal_levels: [f32; 8],
 }
 
 impl Aircraft {
-    pub fn new(icao: Icao) -> Self {
+    pub fn new(icao: Icao, now: Timestamp) -> Self {
         Aircraft {
             icao,
             callsign: None,
+            category: None,
+            squawk: None,
+            on_ground: None,
+            alert: None,
+            spi: None,
+            callsign_history: Vec::new(),
             even_cprlat: 0,
             even_cprlon: 0,
-            even_cprtime: Instant::now(),
+            even_cprtime: now,
             odd_cprlat: 0,
             odd_cprlon: 0,
-            odd_cprtime: Instant::now(),
-            altitude: None,
-            latitude: 0.0,
-            longitude: 0.0,
-            last_pos_update: Instant::now(),
+            odd_cprtime: now,
+            cpr_surface: false,
+            altitude_baro: None,
+            altitude_geom: None,
+            position: None,
             latitude_interpolated: 0.0,
             longitude_interpolated: 0.0,
-            path: Vec::new(),
+            path: TrackPath::default(),
             velocity_kts: None,
+            vertical_rate: None,
+            first_seen: now,
+            last_seen: now,
+            messages: MessageCounters::default(),
+            seen_by: BTreeMap::new(),
+            signal_levels: [0.0; 8],
+        }
+    }
+
+    /// Account for a frame received from this aircraft
+    pub fn record_frame(&mut self, frame: &Frame) {
+        self.last_seen = frame.timestamp;
+        self.seen_by.insert(frame.source, frame.timestamp);
+        self.signal_levels[self.messages.total as usize % self.signal_levels.len()] =
+            frame.signal_level;
+        self.messages.total += 1;
+        self.messages.by_downlink_format[frame.downlink_format() as usize] += 1;
+    }
+
+    /// Average signal strength of the most recent frames, in dBFS
+    pub fn rssi(&self) -> f64 {
+        let count = (self.messages.total as usize).clamp(1, self.signal_levels.len());
+        let mean = self.signal_levels[..count].iter().sum::<f32>() as f64 / count as f64;
+        10.0 * (mean + 1e-5).log10()
+    }
+
+    /// Returns true if the callsign differs from the previous one
+    pub fn update_callsign(
+        &mut self,
+        callsign: Callsign,
+        now: Timestamp,
+        source: DataSource,
+    ) -> bool {
+        let changed = self.callsign.is_none_or(|c| c.value != callsign);
+        if changed {
+            self.callsign_history.push((callsign, now));
         }
+
+        self.callsign = Some(Tracked::new(callsign, now, source));
+        changed
     }
 
     pub fn latlong(&self) -> Option<(f64, f64)> {
-        (self.latitude != 0.0 && self.longitude != 0.0).then_some((self.latitude, self.longitude))
+        self.position.map(|p| p.value)
     }
 
-    pub fn update_latlong(&mut self) {
-        let last_even = self.even_cprtime.elapsed();
-        let last_odd = self.odd_cprtime.elapsed();
-        let delta = (last_even.as_millis() as i128 - last_odd.as_millis() as i128).abs();
+    /// The barometric altitude if known, otherwise the geometric altitude
+    pub fn altitude(&self) -> Option<Tracked<Altitude>> {
+        [self.altitude_baro, self.altitude_geom]
+            .into_iter()
+            .flatten()
+            .find(|a| a.value.is_known())
+    }
+
+    /// Whether the aircraft is squawking one of the emergency codes (7500, 7600 or 7700)
+    pub fn emergency(&self) -> Option<bool> {
+        self.squawk
+            .map(|s| matches!(s.value, 0x7500 | 0x7600 | 0x7700))
+    }
+
+    /// Store one half of a CPR position pair and try to decode the position.
+    ///
+    /// Surface positions are decoded near the last known position, or near `receiver` if there is none yet
+    pub fn update_cpr(
+        &mut self,
+        is_odd_frame: bool,
+        surface: bool,
+        encoded_latitude: u32,
+        encoded_longitude: u32,
+        now: Timestamp,
+        receiver: Option<(f64, f64)>,
+    ) {
+        // Airborne and surface positions use different zone sizes and can't be combined
+        if surface != self.cpr_surface {
+            self.even_cprlat = 0;
+            self.even_cprlon = 0;
+            self.odd_cprlat = 0;
+            self.odd_cprlon = 0;
+            self.cpr_surface = surface;
+        }
+
+        if is_odd_frame {
+            self.odd_cprlat = encoded_latitude;
+            self.odd_cprlon = encoded_longitude;
+            self.odd_cprtime = now;
+        } else {
+            self.even_cprlat = encoded_latitude;
+            self.even_cprlon = encoded_longitude;
+            self.even_cprtime = now;
+        }
+
+        self.update_latlong(now, receiver);
+    }
+
+    fn update_latlong(&mut self, now: Timestamp, receiver: Option<(f64, f64)>) {
+        let delta = self
+            .even_cprtime
+            .saturating_duration_since(self.odd_cprtime)
+            .max(
+                self.odd_cprtime
+                    .saturating_duration_since(self.even_cprtime),
+            );
 
         // Lat/long updates more than 10 seconds apart should not be trusted
-        if delta > 10_000 {
+        if delta > Duration::from_secs(10) {
             return;
         }
 
@@ -99,20 +367,40 @@ impl Aircraft {
             return;
         }
 
-        if let Some(latlon) = cpr::decode_cpr(self) {
-            (self.latitude, self.longitude) = latlon;
-            (self.latitude_interpolated, self.longitude_interpolated) = latlon;
-            self.path.push(latlon);
-            self.last_pos_update = Instant::now();
+        let latlon = if self.cpr_surface {
+            // Surface positions are ambiguous, the last known position (or the receiver's) picks the right one
+            self.path
+                .last()
+                .map(TrackPoint::latlon)
+                .or(receiver)
+                .and_then(|reference| cpr::decode_cpr_surface(self, reference))
+        } else {
+            cpr::decode_cpr(self)
+        };
+
+        if let Some(latlon) = latlon {
+            self.set_position(latlon, now, DataSource::AdsB);
         }
     }
 
+    /// Record a new position fix
+    pub fn set_position(&mut self, latlon: (f64, f64), now: Timestamp, source: DataSource) {
+        self.position = Some(Tracked::new(latlon, now, source));
+        (self.latitude_interpolated, self.longitude_interpolated) = latlon;
+        self.path.push(TrackPoint {
+            lat: latlon.0,
+            lon: latlon.1,
+            altitude: self.altitude().and_then(|a| a.value.feet()),
+            time: now,
+        });
+    }
+
     pub fn speed_kts(&self) -> Option<f64> {
-        self.velocity_kts.map(|v| v.length())
+        self.velocity_kts.map(|v| v.value.length())
     }
 
     pub fn heading(&self) -> Option<f64> {
-        self.velocity_kts.map(|v| {
+        self.velocity_kts.map(|Tracked { value: v, .. }| {
             let mut a = v.x.atan2(v.y).to_degrees();
             if a < 0.0 {
                 a += 360.0;
@@ -126,7 +414,8 @@ impl Aircraft {
             () => {
                 self.path
                     .iter()
-                    .chain([(self.latitude_interpolated, self.longitude_interpolated)].iter())
+                    .map(TrackPoint::latlon)
+                    .chain([(self.latitude_interpolated, self.longitude_interpolated)])
             };
         }
 
@@ -160,20 +449,26 @@ impl Aircraft {
             .map(|h| format!("{h:.0} deg"))
             .unwrap_or("pending".to_string());
 
+        let altitude = self
+            .altitude()
+            .map(|a| a.to_string())
+            .unwrap_or("pending".to_string());
+
         let data = rerun::AnyValues::default()
             .with_component::<rerun::components::Text>(
                 "callsign",
                 vec![
                     self.callsign
-                        .clone()
+                        .map(|c| c.to_string())
                         .unwrap_or_else(|| "pending".to_string()),
                 ],
             )
             .with_component::<rerun::components::Text>("speed_kts", vec![speed_kts])
             .with_component::<rerun::components::Text>("heading", vec![heading])
+            .with_component::<rerun::components::Text>("altitude", vec![altitude])
             .with_component::<rerun::components::LatLon>(
                 "latlong",
-                vec![(self.latitude, self.longitude)],
+                vec![self.latlong().unwrap_or_default()],
             );
         rec.log(ent_path, &data)?;
 
@@ -181,24 +476,22 @@ impl Aircraft {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum Altitude {
-    Feet(u32),
-    Meters(u32),
-}
-
-impl Altitude {
-    pub fn to_feet(self) -> Self {
-        match self {
-            Self::Feet(feet) => Self::Feet(feet),
-            Self::Meters(meters) => Self::Feet((meters as f32 * 3.28084) as u32),
-        }
-    }
+#[test]
+fn test_track_path() {
+    let point = |i: usize| TrackPoint {
+        lat: i as f64,
+        lon: 0.0,
+        altitude: None,
+        time: Timestamp::default(),
+    };
+    let mut path = TrackPath::default();
+    (0..PATH_CHUNK + 10).for_each(|i| path.push(point(i)));
 
-    pub fn to_meters(self) -> Self {
-        match self {
-            Self::Feet(feet) => Self::Meters((feet as f32 / 3.28084) as u32),
-            Self::Meters(meters) => Self::Meters(meters),
-        }
-    }
+    // The full chunk is shared with the copy, and the copy doesn't see later positions
+    let copy = path.clone();
+    assert!(Arc::ptr_eq(&path.chunks[0], &copy.chunks[0]));
+    path.push(point(PATH_CHUNK + 10));
+    assert_eq!(copy.len(), PATH_CHUNK + 10);
+    assert_eq!(path.last(), Some(&point(PATH_CHUNK + 10)));
+    assert!(path.iter().enumerate().all(|(i, p)| p.lat == i as f64));
 }
@@ -0,0 +1,237 @@
+use std::fmt::Display;
+
+const FEET_PER_METER: f64 = 3.28084;
+
+/// The reference an altitude is measured against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AltitudeKind {
+    /// Pressure altitude referenced to the standard atmosphere (1013.25 hPa)
+    Barometric,
+    /// GNSS height above the WGS-84 ellipsoid
+    Geometric,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Altitude {
+    /// Altitude was not available (all-zero field) or used an invalid encoding
+    Unknown,
+    Feet(AltitudeKind, i32),
+    Meters(AltitudeKind, i32),
+}
+
+impl Altitude {
+    /// Decode the 12-bit altitude field of an airborne position message (TC 9-18 and 20-22).
+    ///
+    /// The Q bit (bit 4) selects between the 25ft increment encoding and 100ft Gillham (Mode C) encoding.
+    pub fn decode_ac12(field: u32, kind: AltitudeKind) -> Self {
+        if field == 0 {
+            return Self::Unknown;
+        }
+
+        let q = field & 0x10 != 0;
+        if q {
+            // N is the 11 bit integer resulting from the removal of the Q bit
+            let n = ((field & 0x0FE0) >> 1) | (field & 0x000F);
+            Self::Feet(kind, n as i32 * 25 - 1000)
+        } else {
+            // Insert M=0 at bit 6 to turn the field into a 13-bit Gillham coded altitude
+            let ac13 = ((field & 0x0FC0) << 1) | (field & 0x003F);
+            match gillham_to_hundreds(id13_to_mode_a(ac13)) {
+                Some(hundreds) => Self::Feet(kind, hundreds * 100),
+                None => Self::Unknown,
+            }
+        }
+    }
+
+    /// Decode the 13-bit altitude code (AC) field of surveillance replies (DF 0, 4, 16 and 20).
+    ///
+    /// These are always barometric. The M bit (bit 6) selects metric units.
+    pub fn decode_ac13(field: u32) -> Self {
+        if field == 0 {
+            return Self::Unknown;
+        }
+
+        let m = field & 0x40 != 0;
+        let q = field & 0x10 != 0;
+        if m {
+            // The remaining 12 bits are the altitude in meters
+            let n = ((field & 0x1F80) >> 1) | (field & 0x003F);
+            Self::Meters(AltitudeKind::Barometric, n as i32)
+        } else if q {
+            // N is the 11 bit integer resulting from the removal of the M and Q bits
+            let n = ((field & 0x1F80) >> 2) | ((field & 0x0020) >> 1) | (field & 0x000F);
+            Self::Feet(AltitudeKind::Barometric, n as i32 * 25 - 1000)
+        } else {
+            match gillham_to_hundreds(id13_to_mode_a(field)) {
+                Some(hundreds) => Self::Feet(AltitudeKind::Barometric, hundreds * 100),
+                None => Self::Unknown,
+            }
+        }
+    }
+
+    pub fn kind(self) -> Option<AltitudeKind> {
+        match self {
+            Self::Unknown => None,
+            Self::Feet(kind, _) | Self::Meters(kind, _) => Some(kind),
+        }
+    }
+
+    pub fn is_known(self) -> bool {
+        !matches!(self, Self::Unknown)
+    }
+
+    pub fn to_feet(self) -> Self {
+        match self {
+            Self::Unknown => Self::Unknown,
+            Self::Feet(kind, feet) => Self::Feet(kind, feet),
+            Self::Meters(kind, meters) => {
+                Self::Feet(kind, (meters as f64 * FEET_PER_METER).round() as i32)
+            }
+        }
+    }
+
+    pub fn to_meters(self) -> Self {
+        match self {
+            Self::Unknown => Self::Unknown,
+            Self::Feet(kind, feet) => {
+                Self::Meters(kind, (feet as f64 / FEET_PER_METER).round() as i32)
+            }
+            Self::Meters(kind, meters) => Self::Meters(kind, meters),
+        }
+    }
+
+    /// The altitude in feet, if known
+    pub fn feet(self) -> Option<i32> {
+        match self.to_feet() {
+            Self::Feet(_, feet) => Some(feet),
+            _ => None,
+        }
+    }
+
+    /// The altitude in meters, if known
+    pub fn meters(self) -> Option<i32> {
+        match self.to_meters() {
+            Self::Meters(_, meters) => Some(meters),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Altitude {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind() {
+            Some(AltitudeKind::Barometric) => "baro",
+            Some(AltitudeKind::Geometric) => "geom",
+            None => return write!(f, "unknown"),
+        };
+
+        match self {
+            Self::Feet(_, feet) => write!(f, "{feet} ft ({kind})"),
+            Self::Meters(_, meters) => write!(f, "{meters} m ({kind})"),
+            Self::Unknown => unreachable!(),
+        }
+    }
+}
+
+/// Rearrange a 13-bit identity/altitude field (C1 A1 C2 A2 C4 A4 X B1 D1 B2 D2 B4 D4) into a Mode A code,
+/// where each of the A, B, C and D digits occupies one nibble (eg. squawk 7700 becomes 0x7700)
+pub fn id13_to_mode_a(id13: u32) -> u16 {
+    const BITS: [(u32, u16); 12] = [
+        (0x1000, 0x0010), // C1
+        (0x0800, 0x1000), // A1
+        (0x0400, 0x0020), // C2
+        (0x0200, 0x2000), // A2
+        (0x0100, 0x0040), // C4
+        (0x0080, 0x4000), // A4
+        (0x0020, 0x0100), // B1
+        (0x0010, 0x0001), // D1
+        (0x0008, 0x0200), // B2
+        (0x0004, 0x0002), // D2
+        (0x0002, 0x0400), // B4
+        (0x0001, 0x0004), // D4
+    ];
+
+    BITS.iter()
+        .filter(|(from, _)| id13 & from != 0)
+        .fold(0, |mode_a, (_, to)| mode_a | to)
+}
+
+/// Decode a Gillham coded altitude (in Mode A nibble layout) to hundreds of feet.
+///
+/// Returns `None` for illegal codes.
+pub fn gillham_to_hundreds(mode_a: u16) -> Option<i32> {
+    // D1 is never used for altitude, and at least one of the C bits must be set
+    if mode_a & 0x8889 != 0 || mode_a & 0x00F0 == 0 {
+        return None;
+    }
+
+    let mut one_hundreds = 0u32;
+    if mode_a & 0x0010 != 0 {
+        one_hundreds ^= 0x007; // C1
+    }
+    if mode_a & 0x0020 != 0 {
+        one_hundreds ^= 0x003; // C2
+    }
+    if mode_a & 0x0040 != 0 {
+        one_hundreds ^= 0x001; // C4
+    }
+
+    // The C bits use a 5-cycle reflected code, swap 7 and 5 around
+    if one_hundreds & 5 == 5 {
+        one_hundreds ^= 2;
+    }
+
+    if one_hundreds > 5 {
+        return None;
+    }
+
+    let mut five_hundreds = 0u32;
+    for (mask, value) in [
+        (0x0002, 0x0FF), // D2
+        (0x0004, 0x07F), // D4
+        (0x1000, 0x03F), // A1
+        (0x2000, 0x01F), // A2
+        (0x4000, 0x00F), // A4
+        (0x0100, 0x007), // B1
+        (0x0200, 0x003), // B2
+        (0x0400, 0x001), // B4
+    ] {
+        if mode_a & mask != 0 {
+            five_hundreds ^= value;
+        }
+    }
+
+    // The 100ft increments count down for odd 500ft increments
+    if five_hundreds & 1 != 0 {
+        one_hundreds = 6 - one_hundreds;
+    }
+
+    Some((five_hundreds * 5 + one_hundreds) as i32 - 13)
+}
+
+#[test]
+fn test_decode_ac12() {
+    // 8D40621D58C382D690C8AC2863A7
+    assert_eq!(
+        Altitude::decode_ac12(0xC38, AltitudeKind::Barometric),
+        Altitude::Feet(AltitudeKind::Barometric, 38000)
+    );
+    // Airports below sea level
+    assert_eq!(
+        Altitude::decode_ac12(0x010, AltitudeKind::Barometric),
+        Altitude::Feet(AltitudeKind::Barometric, -1000)
+    );
+    assert_eq!(
+        Altitude::decode_ac12(0, AltitudeKind::Geometric),
+        Altitude::Unknown
+    );
+}
+
+#[test]
+fn test_gillham() {
+    assert_eq!(gillham_to_hundreds(0x0040), Some(-12));
+    assert_eq!(gillham_to_hundreds(0x0000), None);
+    // D1 is not a valid altitude bit
+    assert_eq!(gillham_to_hundreds(0x0041), None);
+    assert_eq!(id13_to_mode_a(0b1_0_1_0_1_0_0_0_0_0_0_0_0), 0x0070);
+}
@@ -0,0 +1,174 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{export::TrackFormat, magnitude::IqFormat, net::input::InputSpec};
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Serve decoded frames in Beast binary format on this TCP port (usually 30005)
+    #[arg(long, value_name = "PORT")]
+    pub net_beast_port: Option<u16>,
+
+    /// Serve decoded frames in AVR text format on this TCP port (usually 30002)
+    #[arg(long, value_name = "PORT")]
+    pub net_avr_port: Option<u16>,
+
+    /// Print decoded frames in AVR text format to stdout
+    #[arg(long)]
+    pub avr_stdout: bool,
+
+    /// Prefix AVR frames with their MLAT timestamp (`@` instead of `*`)
+    #[arg(long)]
+    pub avr_mlat: bool,
+
+    /// Also output frames that needed error correction in AVR format
+    #[arg(long)]
+    pub avr_corrected: bool,
+
+    /// Serve aircraft updates in BaseStation (SBS-1) format on this TCP port (usually 30003)
+    #[arg(long, value_name = "PORT")]
+    pub net_sbs_port: Option<u16>,
+
+    /// Serve aircraft state and statistics as JSON over HTTP on this TCP port
+    #[arg(long, value_name = "PORT")]
+    pub net_http_port: Option<u16>,
+
+    /// Push decoded messages and aircraft changes as JSON over WebSocket on this TCP port
+    #[arg(long, value_name = "PORT")]
+    pub net_ws_port: Option<u16>,
+
+    /// Periodically write aircraft.json, receiver.json and stats.json into this directory
+    #[arg(long, value_name = "DIR")]
+    pub write_json: Option<PathBuf>,
+
+    /// How often to update the JSON files, in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 1.0)]
+    pub write_json_every: f64,
+
+    /// Write the track of every lost aircraft to a file in this directory
+    #[arg(long, value_name = "DIR")]
+    pub export_tracks: Option<PathBuf>,
+
+    /// Formats to write with --export-tracks
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_delimiter = ',',
+        default_value = "geojson"
+    )]
+    pub export_format: Vec<TrackFormat>,
+
+    /// Connect to another receiver and decode its frames, as FORMAT:HOST:PORT with FORMAT one of beast, avr or sbs
+    /// (eg. beast:192.168.1.10:30005). Can be given multiple times
+    #[arg(long, value_name = "FORMAT:HOST:PORT")]
+    pub net_connect: Vec<InputSpec>,
+
+    /// Accept connections from receivers pushing frames to us, as FORMAT:PORT (eg. beast:30004). Can be given
+    /// multiple times
+    #[arg(long, value_name = "FORMAT:PORT")]
+    pub net_listen: Vec<InputSpec>,
+
+    /// Read a Beast, AVR or SBS capture file as FORMAT:PATH (eg. beast:capture.bin). Can be given multiple times
+    #[arg(long, value_name = "FORMAT:PATH")]
+    pub input_file: Vec<InputSpec>,
+
+    /// Drop frames identical to one received (by any source) less than this many milliseconds earlier, 0 to disable
+    #[arg(long, value_name = "MS", default_value_t = 200)]
+    pub dedup_window_ms: u64,
+
+    /// Forget the position of an aircraft after this many seconds without a new one
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    pub position_timeout: u64,
+
+    /// Forget the velocity of an aircraft after this many seconds without a new one
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    pub velocity_timeout: u64,
+
+    /// Stop tracking an aircraft after this many seconds without any message from it
+    #[arg(long, value_name = "SECONDS", default_value_t = 300)]
+    pub target_timeout: u64,
+
+    /// Latitude of the receiver, used to decode the surface positions of aircraft first seen on the ground
+    #[arg(
+        long,
+        value_name = "DEGREES",
+        requires = "lon",
+        allow_negative_numbers = true
+    )]
+    pub lat: Option<f64>,
+
+    /// Longitude of the receiver
+    #[arg(
+        long,
+        value_name = "DEGREES",
+        requires = "lat",
+        allow_negative_numbers = true
+    )]
+    pub lon: Option<f64>,
+
+    /// Record every valid frame to this file, as JSON lines
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+
+    /// Decode the frames recorded with --record instead of receiving any
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["net_connect", "net_listen", "input_file"])]
+    pub replay: Option<PathBuf>,
+
+    /// Replay this many times faster than the frames were recorded, or 0 to replay as fast as possible
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value_t = 1.0,
+        value_parser = parse_replay_speed,
+        allow_negative_numbers = true
+    )]
+    pub replay_speed: f64,
+
+    /// SDR sample rate in S/s. At least 2000000, 2400000 gives the best results on RTL-SDR dongles
+    #[arg(long, value_name = "RATE", default_value_t = 2_400_000)]
+    pub sample_rate: u32,
+
+    /// Number of threads demodulating SDR samples
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub demod_threads: usize,
+
+    /// Minimum ratio between the preamble pulses and the quiet samples around them, in dB
+    #[arg(long, value_name = "DB", default_value_t = 6.0)]
+    pub preamble_snr: f32,
+
+    /// Demodulate raw IQ samples from this file instead of the SDR, recorded at --sample-rate
+    #[arg(long, value_name = "PATH", conflicts_with = "no_sdr")]
+    pub iq_file: Option<PathBuf>,
+
+    /// Sample format of --iq-file
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = IqFormat::Cu8)]
+    pub iq_format: IqFormat,
+
+    /// Also demodulate Mode A/C replies, sent to the Beast output and counted in the statistics
+    #[arg(long)]
+    pub mode_ac: bool,
+
+    /// Minimum ratio between the weakest framing pulse of a Mode A/C reply and the loudest gap between its pulses, in
+    /// dB. Higher than --preamble-snr, as there is no CRC to catch false replies
+    #[arg(long, value_name = "DB", default_value_t = 12.0)]
+    pub mode_ac_snr: f32,
+
+    /// Match Mode A/C replies against the squawks of the aircraft tracked through Mode S
+    #[arg(long, requires = "mode_ac")]
+    pub mode_ac_correlate: bool,
+
+    /// Don't open the SDR, only decode network and file inputs
+    #[arg(long)]
+    pub no_sdr: bool,
+}
+
+fn parse_replay_speed(s: &str) -> Result<f64, String> {
+    let speed = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if speed >= 0.0 {
+        Ok(speed)
+    } else {
+        Err("the replay speed can't be negative".to_string())
+    }
+}
@@ -53,6 +53,71 @@ pub fn decode_cpr(a: &Aircraft) -> Option<(f64, f64)> {
     Some((lat, lon))
 }
 
+/// Decode a surface position pair. Surface positions use zones a quarter of the size of airborne positions, which
+/// leaves four possible solutions. The one closest to `reference` is picked
+pub fn decode_cpr_surface(a: &Aircraft, reference: (f64, f64)) -> Option<(f64, f64)> {
+    let surface_dlat0 = 90.0 / 60.0;
+    let surface_dlat1 = 90.0 / 59.0;
+
+    let lat0 = a.even_cprlat as f64;
+    let lat1 = a.odd_cprlat as f64;
+
+    let lon0 = a.even_cprlon as f64;
+    let lon1 = a.odd_cprlon as f64;
+
+    let j = (((59.0 * lat0 - 60.0 * lat1) / 131072.0) + 0.5).floor() as i32;
+    let rlat0 = surface_dlat0 * (cpr_mod(j, 60) as f64 + lat0 / 131072.0);
+    let rlat1 = surface_dlat1 * (cpr_mod(j, 59) as f64 + lat1 / 131072.0);
+
+    // Pick the northern or southern hemisphere solution closest to the reference
+    let pick_lat = |rlat: f64| {
+        if (rlat - 90.0 - reference.0).abs() < (rlat - reference.0).abs() {
+            rlat - 90.0
+        } else {
+            rlat
+        }
+    };
+    let rlat0 = pick_lat(rlat0);
+    let rlat1 = pick_lat(rlat1);
+
+    if cpr_nl(rlat0) != cpr_nl(rlat1) {
+        return None;
+    }
+
+    let (rlat, lon, is_odd) = if a.even_cprtime > a.odd_cprtime {
+        (rlat0, lon0, false)
+    } else {
+        (rlat1, lon1, true)
+    };
+
+    let ni = cpr_n(rlat, is_odd);
+    let m = ((((lon0 * (cpr_nl(rlat) - 1) as f64) - (lon1 * cpr_nl(rlat) as f64)) / 131072.0) + 0.5)
+        .floor() as i32;
+
+    let dlon = cpr_dlon(rlat, is_odd) / 4.0;
+    let lon = dlon * (cpr_mod(m, ni) as f64 + lon / 131072.0);
+
+    // The longitude repeats every 90 degrees
+    let lon = (0..4)
+        .map(|i| {
+            let lon = lon + i as f64 * 90.0;
+            if lon > 180.0 { lon - 360.0 } else { lon }
+        })
+        .min_by(|a, b| {
+            let da = angle_difference(*a, reference.1);
+            let db = angle_difference(*b, reference.1);
+            da.total_cmp(&db)
+        })?;
+
+    Some((rlat, lon))
+}
+
+/// Absolute difference between two longitudes, taking the antimeridian into account
+fn angle_difference(a: f64, b: f64) -> f64 {
+    let d = (a - b).abs() % 360.0;
+    if d > 180.0 { 360.0 - d } else { d }
+}
+
 fn cpr_mod(a: i32, b: i32) -> i32 {
     let mut res = a % b;
     if res < 0 {
@@ -222,3 +287,42 @@ fn test_cpr_nl() {
     assert_eq!(cpr_nl(86.6), 2);
     assert_eq!(cpr_nl(88.0), 1);
 }
+
+#[test]
+fn test_cpr_surface() {
+    use crate::{aircraft::Icao, timestamp::Timestamp};
+
+    // 8C4841753AAB238733C8CD4020B1 (even) and 8C4841753A8A35323FAEBDAC702D (odd), near Amsterdam
+    let mut a = Aircraft::new(Icao::new(0x484175), Timestamp::default());
+    (a.even_cprlat, a.even_cprlon) = (115609, 116941);
+    (a.odd_cprlat, a.odd_cprlon) = (39199, 110269);
+    a.odd_cprtime = Timestamp::from_ticks(12_000_000);
+
+    let close = |(lat, lon): (f64, f64), expected: (f64, f64)| {
+        (lat - expected.0).abs() < 1e-4 && (lon - expected.1).abs() < 1e-4
+    };
+    let position = decode_cpr_surface(&a, (51.990, 4.375)).unwrap();
+    assert!(close(position, (52.32061, 4.73473)), "{position:?}");
+
+    // The same messages near a receiver 90 degrees further east, or in the southern hemisphere (where the longitude
+    // zones have a different size)
+    let position = decode_cpr_surface(&a, (51.990, 100.0)).unwrap();
+    assert!(close(position, (52.32061, 94.73473)), "{position:?}");
+    let (lat, _) = decode_cpr_surface(&a, (-30.0, 4.375)).unwrap();
+    assert!((lat - (52.32061 - 90.0)).abs() < 1e-4, "{lat}");
+
+    // An aircraft first seen on the ground is decoded near the receiver
+    let mut b = Aircraft::new(a.icao, Timestamp::default());
+    b.update_cpr(false, true, 115609, 116941, a.even_cprtime, None);
+    b.update_cpr(true, true, 39199, 110269, a.odd_cprtime, None);
+    assert_eq!(b.latlong(), None);
+    b.update_cpr(
+        true,
+        true,
+        39199,
+        110269,
+        a.odd_cprtime,
+        Some((51.990, 4.375)),
+    );
+    assert!(close(b.latlong().unwrap(), (52.32061, 4.73473)));
+}
@@ -0,0 +1,251 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+const MODE_S_CHECKSUM_LUT: [u32; 112] = [
+    0x3935ea, 0x1c9af5, 0xf1b77e, 0x78dbbf, 0xc397db, 0x9e31e9, 0xb0e2f0, 0x587178, 0x2c38bc,
+    0x161c5e, 0x0b0e2f, 0xfa7d13, 0x82c48d, 0xbe9842, 0x5f4c21, 0xd05c14, 0x682e0a, 0x341705,
+    0xe5f186, 0x72f8c3, 0xc68665, 0x9cb936, 0x4e5c9b, 0xd8d449, 0x939020, 0x49c810, 0x24e408,
+    0x127204, 0x093902, 0x049c81, 0xfdb444, 0x7eda22, 0x3f6d11, 0xe04c8c, 0x702646, 0x381323,
+    0xe3f395, 0x8e03ce, 0x4701e7, 0xdc7af7, 0x91c77f, 0xb719bb, 0xa476d9, 0xadc168, 0x56e0b4,
+    0x2b705a, 0x15b82d, 0xf52612, 0x7a9309, 0xc2b380, 0x6159c0, 0x30ace0, 0x185670, 0x0c2b38,
+    0x06159c, 0x030ace, 0x018567, 0xff38b7, 0x80665f, 0xbfc92b, 0xa01e91, 0xaff54c, 0x57faa6,
+    0x2bfd53, 0xea04ad, 0x8af852, 0x457c29, 0xdd4410, 0x6ea208, 0x375104, 0x1ba882, 0x0dd441,
+    0xf91024, 0x7c8812, 0x3e4409, 0xe0d800, 0x706c00, 0x383600, 0x1c1b00, 0x0e0d80, 0x0706c0,
+    0x038360, 0x01c1b0, 0x00e0d8, 0x00706c, 0x003836, 0x001c1b, 0xfff409, 0x000000, 0x000000,
+    0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000,
+    0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000,
+    0x000000, 0x000000, 0x000000, 0x000000,
+];
+
+/// The Mode S generator polynomial, without its x^24 term
+const POLYNOMIAL: u32 = 0xFFF409;
+
+/// CRC of every byte value followed by two zero bytes, to process a message a byte at a time
+const MODE_S_CHECKSUM_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = (byte as u32) << 16;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x800000 != 0 {
+                (crc << 1) ^ POLYNOMIAL
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc & 0xFFFFFF;
+        byte += 1;
+    }
+    table
+};
+
+/// CRC-24 of a 56 or 112 bit frame, excluding its parity field
+pub fn mode_s_checksum(data: &[u8], bits: usize) -> u32 {
+    data[..bits / 8 - 3].iter().fold(0, |crc, &byte| {
+        ((crc << 8) ^ MODE_S_CHECKSUM_TABLE[((crc >> 16) as u8 ^ byte) as usize]) & 0xFFFFFF
+    })
+}
+
+/// The same checksum a bit at a time, as a reference for the tests and benchmarks
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn mode_s_checksum_bitwise(data: &[u8], bits: usize) -> u32 {
+    let mut crc = 0u32;
+    let offset = if bits == 112 { 0 } else { 112 - 56 };
+
+    for j in 0..bits {
+        let byte = j / 8;
+        let bit = j % 8;
+        let mask = 1 << (7 - bit);
+        if data[byte] & mask != 0 {
+            crc ^= MODE_S_CHECKSUM_LUT[j + offset];
+        }
+    }
+
+    crc
+}
+
+/// The downlink format field, which is never corrected since it decides the length and meaning of the frame
+const DF_BITS: usize = 5;
+
+/// Flipped bits must be among this many least confident bits of the frame, if the bit confidences are known
+const CONFIDENCE_CANDIDATES: usize = 12;
+
+/// Syndromes of every 1 and 2 bit error outside the DF field, for short and long frames
+static SYNDROMES_56: LazyLock<HashMap<u32, Option<Correction>>> =
+    LazyLock::new(|| syndrome_table(56));
+static SYNDROMES_112: LazyLock<HashMap<u32, Option<Correction>>> =
+    LazyLock::new(|| syndrome_table(112));
+
+/// The bits to flip to correct a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Correction {
+    Single(u8),
+    Double(u8, u8),
+}
+
+impl Correction {
+    pub fn bits(self) -> impl Iterator<Item = usize> {
+        let (first, second) = match self {
+            Correction::Single(bit) => (bit, None),
+            Correction::Double(first, second) => (first, Some(second)),
+        };
+        std::iter::once(first).chain(second).map(usize::from)
+    }
+
+    pub fn count(self) -> u8 {
+        match self {
+            Correction::Single(_) => 1,
+            Correction::Double(..) => 2,
+        }
+    }
+}
+
+/// The checksum xor the parity field, zero for an intact frame
+pub fn syndrome(data: &[u8], bits: usize) -> u32 {
+    let end = bits / 8;
+    let parity = (data[end - 3] as u32) << 16 | (data[end - 2] as u32) << 8 | data[end - 1] as u32;
+    mode_s_checksum(data, bits) ^ parity
+}
+
+/// How the syndrome of a `bits` long frame changes when bit `bit` is flipped
+fn bit_syndrome(bit: usize, bits: usize) -> u32 {
+    if bit < bits - 24 {
+        let offset = if bits == 112 { 0 } else { 112 - 56 };
+        MODE_S_CHECKSUM_LUT[bit + offset]
+    } else {
+        1 << (bits - 1 - bit)
+    }
+}
+
+/// Map every syndrome of a single or double bit error to its bits.
+///
+/// Single bit errors win over double ones, syndromes shared by two double errors are ambiguous and map to `None`
+fn syndrome_table(bits: usize) -> HashMap<u32, Option<Correction>> {
+    let mut table = HashMap::new();
+    for bit in DF_BITS..bits {
+        table.insert(bit_syndrome(bit, bits), Some(Correction::Single(bit as u8)));
+    }
+
+    for first in DF_BITS..bits {
+        for second in first + 1..bits {
+            let syndrome = bit_syndrome(first, bits) ^ bit_syndrome(second, bits);
+            let correction = Correction::Double(first as u8, second as u8);
+            table
+                .entry(syndrome)
+                .and_modify(|existing| {
+                    if !matches!(existing, Some(Correction::Single(_))) {
+                        *existing = None;
+                    }
+                })
+                .or_insert(Some(correction));
+        }
+    }
+
+    table
+}
+
+/// Correct up to `max_errors` flipped bits, outside the DF field.
+///
+/// With the demodulator's `confidence` in each bit, only the least confident bits may be flipped.
+/// Returns the correction that was applied
+pub fn fix_errors(
+    data: &mut [u8],
+    bits: usize,
+    max_errors: u8,
+    confidence: Option<&[u8]>,
+) -> Option<Correction> {
+    let table = if bits == 112 {
+        &SYNDROMES_112
+    } else {
+        &SYNDROMES_56
+    };
+    let correction = (*table.get(&syndrome(data, bits))?)?;
+    if correction.count() > max_errors {
+        return None;
+    }
+
+    if let Some(confidence) = confidence {
+        let least_confident = |bit: usize| {
+            confidence[DF_BITS..bits]
+                .iter()
+                .filter(|&&c| c < confidence[bit])
+                .count()
+                < CONFIDENCE_CANDIDATES
+        };
+        if !correction.bits().all(least_confident) {
+            return None;
+        }
+    }
+
+    for bit in correction.bits() {
+        data[bit / 8] ^= 1 << (7 - bit % 8);
+    }
+    Some(correction)
+}
+
+#[test]
+fn test_fix_errors() {
+    let frame = [
+        0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+    ];
+    let mut data = frame;
+    data[5] ^= 0x10; // bit 43
+    data[12] ^= 0x01; // bit 103, in the parity field
+    assert_eq!(fix_errors(&mut data, 112, 1, None), None);
+    assert_eq!(
+        fix_errors(&mut data, 112, 2, None),
+        Some(Correction::Double(43, 103))
+    );
+    assert_eq!(data, frame);
+
+    // Only when the demodulator wasn't sure about those bits
+    data[5] ^= 0x10;
+    data[12] ^= 0x01;
+    let mut confidence = [200u8; 112];
+    confidence[43] = 5;
+    confidence[103] = 250;
+    assert_eq!(fix_errors(&mut data, 112, 2, Some(&confidence)), None);
+    confidence[103] = 5;
+    assert!(fix_errors(&mut data, 112, 2, Some(&confidence)).is_some());
+    assert_eq!(data, frame);
+
+    // Never the DF field
+    data[0] ^= 0x20;
+    assert_eq!(fix_errors(&mut data, 112, 2, None), None);
+
+    let short = [0x5D, 0x48, 0x40, 0xD6, 0xF8, 0x74, 0x0F];
+    let mut data = short;
+    data[3] ^= 0x04;
+    assert_eq!(
+        fix_errors(&mut data, 56, 1, None),
+        Some(Correction::Single(29))
+    );
+    assert_eq!(data, short);
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_checksum_matches_bitwise(data: [u8; 14], long: bool) {
+        let bits = if long { 112 } else { 56 };
+        proptest::prop_assert_eq!(
+            mode_s_checksum(&data, bits),
+            mode_s_checksum_bitwise(&data, bits)
+        );
+    }
+
+    #[test]
+    fn test_fix_any_single_error(mut data: [u8; 14], bit in DF_BITS..112) {
+        let crc = mode_s_checksum(&data, 112).to_be_bytes();
+        data[11..].copy_from_slice(&crc[1..]);
+        let frame = data;
+
+        data[bit / 8] ^= 1 << (7 - bit % 8);
+        proptest::prop_assert_eq!(
+            fix_errors(&mut data, 112, 1, None),
+            Some(Correction::Single(bit as u8))
+        );
+        proptest::prop_assert_eq!(data, frame);
+    }
+}
@@ -0,0 +1,403 @@
+use std::borrow::Cow;
+
+use glam::DVec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aircraft::{DataSource, Icao, Tracked},
+    altitude::{Altitude, AltitudeKind, id13_to_mode_a},
+    bitreader::BitReader,
+    crc::mode_s_checksum,
+    frame::Frame,
+    identification::{Callsign, WakeVortexCategory},
+    tracker::Tracker,
+};
+
+/// The kind of information carried by a frame. These follow the BaseStation (SBS) message types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateKind {
+    Identification,
+    SurfacePosition,
+    AirbornePosition,
+    AirborneVelocity,
+    SurveillanceAltitude,
+    SurveillanceIdentity,
+    AirToAir,
+    AllCallReply,
+    /// Any other message from a known aircraft, eg. aircraft status
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Update {
+    pub icao: Icao,
+    pub kind: UpdateKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Decoded {
+    /// Surveillance reply from an aircraft we aren't tracking, so the address/parity could not be verified
+    Unverified,
+    /// Valid frame that can't be attributed to an aircraft (eg. military or Comm-D)
+    Unhandled,
+    Update(Update),
+}
+
+/// Downlink formats where the parity field is overlaid with the aircraft address
+pub fn has_address_parity(downlink_format: u8) -> bool {
+    matches!(downlink_format, 0 | 4 | 5 | 16 | 20 | 21)
+}
+
+/// Decode a frame and update the tracked aircraft state
+pub fn handle_frame(
+    frame: &Frame,
+    tracker: &mut Tracker,
+    rec: &rerun::RecordingStream,
+) -> anyhow::Result<Decoded> {
+    let data = frame.data();
+    let downlink_format = frame.downlink_format();
+    let now = frame.timestamp;
+    let receiver = tracker.config.receiver;
+
+    if has_address_parity(downlink_format) {
+        // Surveillance replies overlay the parity with the aircraft address, so only
+        // replies from aircraft we already know about can be validated
+        let icao = Icao::new(mode_s_checksum(data, frame.bits()) ^ frame.parity());
+        let Some(craft) = tracker.known_aircraft_seen(&icao, frame) else {
+            return Ok(Decoded::Unverified);
+        };
+
+        let source = if matches!(downlink_format, 20 | 21) {
+            DataSource::CommB
+        } else {
+            DataSource::Surveillance
+        };
+
+        if matches!(downlink_format, 0 | 16) {
+            // Vertical status
+            let on_ground = data[0] & 0b100 != 0;
+            craft.on_ground = Some(Tracked::new(on_ground, now, source));
+        } else {
+            let flight_status = data[0] & 0b111;
+            if let Some(on_ground) = match flight_status {
+                0 | 2 => Some(false),
+                1 | 3 => Some(true),
+                _ => None,
+            } {
+                craft.on_ground = Some(Tracked::new(on_ground, now, source));
+            }
+
+            let alert = matches!(flight_status, 2..=4);
+            let spi = matches!(flight_status, 4 | 5);
+            craft.alert = Some(Tracked::new(alert, now, source));
+            craft.spi = Some(Tracked::new(spi, now, source));
+        }
+
+        let field13 = (data[2] as u32 & 0x1F) << 8 | data[3] as u32;
+        let kind = if matches!(downlink_format, 5 | 21) {
+            let squawk = id13_to_mode_a(field13);
+            craft.squawk = Some(Tracked::new(squawk, now, source));
+            info!("ICAO: {icao}, DF: {downlink_format}, Squawk: {squawk:04X}");
+            UpdateKind::SurveillanceIdentity
+        } else {
+            let altitude = Altitude::decode_ac13(field13);
+            craft.altitude_baro = Some(Tracked::new(altitude, now, source));
+            info!("ICAO: {icao}, DF: {downlink_format}, Altitude: {altitude}");
+            if matches!(downlink_format, 0 | 16) {
+                UpdateKind::AirToAir
+            } else {
+                UpdateKind::SurveillanceAltitude
+            }
+        };
+
+        // BDS 2,0 (aircraft identification) is the only Comm-B register we decode
+        if source == DataSource::CommB && data[4] == 0x20 {
+            let mut mb = BitReader::new(Cow::Borrowed(&data[5..11]));
+            let codes = std::array::from_fn(|_| mb.read_bits(6) as u8);
+            if let Some(callsign) = Callsign::from_ais(codes) {
+                info!("  Callsign (Comm-B): {callsign}");
+                craft.update_callsign(callsign, now, source);
+            }
+        }
+
+        return Ok(Decoded::Update(Update { icao, kind }));
+    }
+
+    let mode = match downlink_format {
+        0 => Some("Short air-air surveillance (ACAS)"),
+        4 => Some("Surveillance, altitude reply"),
+        5 => Some("Surveillance, identity reply"),
+        11 => Some("All-Call reply"),
+        16 => Some("Long air-air surveillance (ACAS)"),
+        17 => Some("Extended squitter"),
+        18 => Some("Extended squitter/non transponder"),
+        19 => Some("Military extended squitter"),
+        20 => Some("Comm-B, altitude reply"),
+        21 => Some("Comm-B, identity reply"),
+        // "Format number 24 is identified using only the first two bits, which must be 11 in binary. All following bits are used for encoding other information"
+        24..=31 => Some("Comm-D (ELM)"),
+        _ => None,
+    };
+    info!("  Mode: {mode:?} ({downlink_format})");
+    let (icao, craft) = if matches!(downlink_format, 11 | 17 | 18) {
+        let ca = data[0] & 0b111;
+        let icao = Icao::new((data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32);
+        info!("ICAO: {icao}, DF: {downlink_format}, CA: {ca:02X}");
+        let craft = tracker.aircraft_seen(icao, frame);
+
+        // The capability field of DF18 has a different meaning (control field)
+        if downlink_format != 18 {
+            match ca {
+                4 => craft.on_ground = Some(Tracked::new(true, now, DataSource::AdsB)),
+                5 => craft.on_ground = Some(Tracked::new(false, now, DataSource::AdsB)),
+                _ => {}
+            }
+        }
+
+        (icao, craft)
+    } else {
+        warn!("Unhandled Mode S DF {downlink_format}");
+        return Ok(Decoded::Unhandled);
+    };
+
+    let update = |kind| -> anyhow::Result<Decoded> { Ok(Decoded::Update(Update { icao, kind })) };
+    if downlink_format == 11 {
+        return update(UpdateKind::AllCallReply);
+    }
+
+    let mut msg = BitReader::new(Cow::Borrowed(&data[4..11]));
+    let message_type = msg.read_bits(5);
+    let message_typename = match message_type {
+        1..=4 => "Aircraft identification",
+        5..=8 => "Surface position",
+        9..=18 => "Airborne position (w/Baro Altitude)",
+        19 => "Airborne velocities",
+        20..=22 => "Airborne position (w/GNSS Height)",
+        23..=27 => "Reserved",
+        28 => "Aircraft status",
+        29 => "Target state and status information",
+        31 => "Aircraft operation status",
+        _ => {
+            error!("Unknown message type: {}", message_type);
+            return update(UpdateKind::Other);
+        }
+    };
+
+    craft.messages.by_type_code[message_type as usize] += 1;
+    info!("ADS-B message '{message_typename}' ({message_type})");
+    info!(
+        "ADS-B message data: {}",
+        msg.data()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join("")
+    );
+
+    match message_type {
+        1..=4 => {
+            let category = WakeVortexCategory::new(message_type, msg.read_bits(3));
+            info!("  Aircraft category: {}", category);
+            craft.category = Some(Tracked::new(category, now, DataSource::AdsB));
+
+            let codes = std::array::from_fn(|_| msg.read_bits(6) as u8);
+            let Some(callsign) = Callsign::from_ais(codes) else {
+                warn!("  Invalid callsign characters: {codes:?}");
+                return update(UpdateKind::Other);
+            };
+
+            info!("  Callsign: {}", callsign);
+            if craft.update_callsign(callsign, now, DataSource::AdsB) {
+                rec.log(
+                    "logs",
+                    &rerun::TextLog::new(format!(
+                        "Craft {} updated callsign to {callsign}",
+                        craft.icao
+                    ))
+                    .with_level(rerun::TextLogLevel::DEBUG),
+                )?;
+            }
+
+            update(UpdateKind::Identification)
+        }
+        5..=8 => {
+            let movement = msg.read_bits(7);
+            let track_valid = msg.read_bit();
+            let track = msg.read_bits(7) as f64 * 360.0 / 128.0;
+            let _time = msg.read_bit();
+            let is_odd_frame = msg.read_bit();
+            let encoded_latitude = msg.read_bits(17);
+            let encoded_longitude = msg.read_bits(17);
+
+            craft.on_ground = Some(Tracked::new(true, now, DataSource::AdsB));
+            craft.update_cpr(
+                is_odd_frame,
+                true,
+                encoded_latitude,
+                encoded_longitude,
+                now,
+                receiver,
+            );
+
+            if let Some(speed) = decode_movement(movement)
+                && track_valid
+            {
+                let track = track.to_radians();
+                let velocity = DVec2::new(speed * track.sin(), speed * track.cos());
+                craft.velocity_kts = Some(Tracked::new(velocity, now, DataSource::AdsB));
+            }
+
+            info!("  Ground speed: {:?} kts", decode_movement(movement));
+            info!("  Lat/Long: {:?}", craft.latlong());
+
+            if craft.latlong().is_some() {
+                craft.log_rerun(rec)?;
+            }
+
+            update(UpdateKind::SurfacePosition)
+        }
+        9..=18 | 20..=22 => {
+            let surveillance_status = msg.read_bits(2);
+            let surveillance_status_name = match surveillance_status {
+                0 => "No condition",
+                1 => "Permanent alert",
+                2 => "Temporary alert",
+                3 => "Special condition",
+                _ => unreachable!(),
+            };
+
+            let _single_antenna = msg.read_bit();
+            let encoded_altitude = msg.read_bits(12);
+            let _time = msg.read_bits(1);
+            let is_odd_frame = msg.read_bit();
+            let encoded_latitude = msg.read_bits(17);
+            let encoded_longitude = msg.read_bits(17);
+
+            craft.on_ground = Some(Tracked::new(false, now, DataSource::AdsB));
+            craft.alert = Some(Tracked::new(
+                matches!(surveillance_status, 1 | 2),
+                now,
+                DataSource::AdsB,
+            ));
+            craft.spi = Some(Tracked::new(
+                surveillance_status == 3,
+                now,
+                DataSource::AdsB,
+            ));
+
+            // Altitude first, so the new position is stored with it
+            let altitude = if (9..=18).contains(&message_type) {
+                let altitude = Altitude::decode_ac12(encoded_altitude, AltitudeKind::Barometric);
+                craft.altitude_baro = Some(Tracked::new(altitude, now, DataSource::AdsB));
+                altitude
+            } else {
+                let altitude = Altitude::decode_ac12(encoded_altitude, AltitudeKind::Geometric);
+                craft.altitude_geom = Some(Tracked::new(altitude, now, DataSource::AdsB));
+                altitude
+            };
+
+            craft.update_cpr(
+                is_odd_frame,
+                false,
+                encoded_latitude,
+                encoded_longitude,
+                now,
+                receiver,
+            );
+
+            info!("  Surveillance status: {}", surveillance_status_name);
+            info!(
+                "  Altitude: {altitude} ({:?} ft, {:?} m)",
+                altitude.feet(),
+                altitude.meters()
+            );
+            info!("  Latitude: {} (not decoded)", encoded_latitude);
+            info!("  Longitude: {} (not decoded)", encoded_longitude);
+            info!("  Lat/Long: {:?}", craft.latlong());
+
+            if craft.latlong().is_some() {
+                craft.log_rerun(rec)?;
+            }
+
+            update(UpdateKind::AirbornePosition)
+        }
+        19 => {
+            let subtype = msg.read_bits(3);
+            let _intent_change = msg.read_bit();
+            let _ifr_capable = msg.read_bit();
+            let _nuc = msg.read_bits(3);
+            match subtype {
+                // Ground-based velocity (despite it's name also applies to airborne aircraft)
+                1 | 2 => {
+                    let horizontal_dir = msg.read_bits(1); // 0=east, 1=west
+                    let horizontal_speed = msg.read_bits(10);
+                    let vertical_dir = msg.read_bits(1); // 0=north, 1=south
+                    let vertical_speed = msg.read_bits(10);
+
+                    // A speed of 0 means no velocity information is available
+                    if horizontal_speed != 0 && vertical_speed != 0 {
+                        let mut horizontal_speed = horizontal_speed - 1;
+                        let mut vertical_speed = vertical_speed - 1;
+
+                        let hsign = if horizontal_dir == 1 { -1 } else { 1 };
+                        let vsign = if vertical_dir == 1 { -1 } else { 1 };
+
+                        // Used for supersonic aircraft (rarely, if ever seen these days)
+                        if subtype == 2 {
+                            horizontal_speed *= 4;
+                            vertical_speed *= 4;
+                        }
+
+                        let velocity = DVec2::new(
+                            horizontal_speed as f64 * hsign as f64,
+                            vertical_speed as f64 * vsign as f64,
+                        );
+
+                        craft.velocity_kts = Some(Tracked::new(velocity, now, DataSource::AdsB));
+                    }
+                }
+                u => {
+                    error!("Unhandled velocity subtype {u}");
+                    let _ = msg.read_bits(22);
+                }
+            }
+
+            if matches!(subtype, 1..=4) {
+                let _vertical_rate_source = msg.read_bit();
+                let descending = msg.read_bit();
+                let vertical_rate = msg.read_bits(9);
+                if vertical_rate != 0 {
+                    let sign = if descending { -1 } else { 1 };
+                    let vertical_rate = (vertical_rate as i32 - 1) * 64 * sign;
+                    info!("  Vertical rate: {vertical_rate} ft/min");
+                    craft.vertical_rate = Some(Tracked::new(vertical_rate, now, DataSource::AdsB));
+                }
+            }
+
+            update(UpdateKind::AirborneVelocity)
+        }
+        u => {
+            error!("Unhandled ADS-B message type: {u}");
+            update(UpdateKind::Other)
+        }
+    }
+}
+
+/// Decode the movement field of a surface position message into a ground speed in knots
+fn decode_movement(movement: u32) -> Option<f64> {
+    let speed = match movement {
+        1 => 0.0,
+        2..=8 => 0.125 + (movement - 2) as f64 * 0.125,
+        9..=12 => 1.0 + (movement - 9) as f64 * 0.25,
+        13..=38 => 2.0 + (movement - 13) as f64 * 0.5,
+        39..=93 => 15.0 + (movement - 39) as f64,
+        94..=108 => 70.0 + (movement - 94) as f64 * 2.0,
+        109..=123 => 100.0 + (movement - 109) as f64 * 5.0,
+        124 => 175.0,
+        // 0 is no information, 125-127 are reserved
+        _ => return None,
+    };
+
+    Some(speed)
+}
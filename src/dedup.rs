@@ -0,0 +1,64 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{frame::Frame, timestamp::Timestamp};
+
+/// Drops copies of a frame that several receivers picked up, or that was demodulated more than once
+pub struct Deduplicator {
+    window: Duration,
+    /// When each frame was first received
+    seen: HashMap<[u8; 14], Timestamp>,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration) -> Self {
+        Deduplicator {
+            window,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Returns true if an identical frame was received within the window
+    pub fn is_duplicate(&mut self, frame: &Frame) -> bool {
+        if self.window.is_zero() {
+            return false;
+        }
+
+        let mut key = [0u8; 14];
+        key[..frame.data().len()].copy_from_slice(frame.data());
+
+        // Sources aren't perfectly in sync, so the copy may have an earlier timestamp than the original
+        let now = frame.timestamp;
+        if let Some(&first) = self.seen.get(&key)
+            && now
+                .saturating_duration_since(first)
+                .max(first.saturating_duration_since(now))
+                <= self.window
+        {
+            return true;
+        }
+
+        self.seen.insert(key, now);
+        false
+    }
+
+    /// Forget frames that are too old to have duplicates arriving
+    pub fn expire(&mut self, now: Timestamp) {
+        let window = self.window;
+        self.seen
+            .retain(|_, first| now.saturating_duration_since(*first) <= window);
+    }
+}
+
+#[test]
+fn test_deduplicator() {
+    let mut dedup = Deduplicator::new(Duration::from_millis(200));
+    let start = Timestamp::default() + Duration::from_secs(1);
+    let data = [0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3];
+
+    assert!(!dedup.is_duplicate(&Frame::new(&data, start, 0.1)));
+    assert!(dedup.is_duplicate(&Frame::new(&data, start + Duration::from_millis(150), 0.2)));
+    assert!(!dedup.is_duplicate(&Frame::new(&data, start + Duration::from_millis(500), 0.1)));
+
+    dedup.expire(start + Duration::from_secs(10));
+    assert!(dedup.seen.is_empty());
+}
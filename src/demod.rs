@@ -0,0 +1,340 @@
+//! Demodulation of Mode S frames from 16 bit magnitude samples, at 2 MS/s or faster.
+//!
+//! Pulses are 0.5 µs long, which doesn't line up with the samples at most sample rates. Instead of reading single
+//! samples, every half microsecond slot is integrated over the samples it overlaps, at several sub-sample phases.
+//! The samples and weights of every slot are worked out once, so demodulation only needs integer arithmetic.
+
+use std::time::Duration;
+
+use crate::{crc::mode_s_checksum, decoder, frame::Frame, magnitude, timestamp::Timestamp};
+
+/// Slots of the preamble that carry a pulse: `1010000101000000`
+const PREAMBLE_HIGH: [usize; 4] = [0, 2, 7, 9];
+/// Slots of the preamble that must be quiet, including the quiet zone before the data
+const PREAMBLE_LOW: [usize; 12] = [1, 3, 4, 5, 6, 8, 10, 11, 12, 13, 14, 15];
+
+/// Number of half microsecond slots in the preamble
+pub const PREAMBLE_SLOTS: usize = 16;
+
+const PREAMBLE_US: f32 = 8.0;
+const LONG_FRAME_BITS: usize = 112;
+
+/// Sub-sample offsets tried for every preamble. At 2.4 MS/s a fifth of a sample is one 12 MHz tick
+pub const PHASES: [f32; 5] = [0.0, 0.2, 0.4, 0.6, 0.8];
+
+/// Most samples a slot can overlap, which limits the sample rate to 20 MS/s
+const MAX_SLOT_SAMPLES: usize = 11;
+pub const MAX_SAMPLE_RATE: u32 = 20_000_000;
+
+/// Weight of a whole sample in a slot
+pub const SAMPLE_WEIGHT: f32 = 256.0;
+
+/// A candidate preamble, with the levels it was detected at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preamble {
+    /// Average magnitude of the four pulses, relative to full scale
+    pub signal: f32,
+    /// Average magnitude of the twelve quiet slots, relative to full scale
+    pub noise: f32,
+}
+
+impl Preamble {
+    pub fn snr_db(&self) -> f32 {
+        20.0 * (self.signal / self.noise.max(f32::EPSILON)).log10()
+    }
+}
+
+/// Look for a preamble in the levels of its 16 half microsecond slots.
+///
+/// Every pulse must be stronger than every quiet slot, and the average pulse must be at least `min_snr_db` above the
+/// average of the quiet slots. `full_scale` is the level of a full scale slot.
+pub fn detect_preamble(
+    levels: &[u32; PREAMBLE_SLOTS],
+    min_snr_db: f32,
+    full_scale: f32,
+) -> Option<Preamble> {
+    let weakest_high = PREAMBLE_HIGH.iter().map(|&i| levels[i]).min()?;
+    let strongest_low = PREAMBLE_LOW.iter().map(|&i| levels[i]).max()?;
+    if weakest_high <= strongest_low {
+        return None;
+    }
+
+    let average = |slots: &[usize]| {
+        slots.iter().map(|&i| levels[i] as f32).sum::<f32>() / slots.len() as f32 / full_scale
+    };
+    let preamble = Preamble {
+        signal: average(&PREAMBLE_HIGH),
+        noise: average(&PREAMBLE_LOW),
+    };
+    (preamble.snr_db() >= min_snr_db).then_some(preamble)
+}
+
+/// A frame demodulated at the best phase
+#[derive(Debug, Clone, Copy)]
+pub struct Demodulated {
+    /// Always 112 bits, short frames only use the first 7 bytes
+    pub data: [u8; 14],
+    pub preamble: Preamble,
+    /// Mean power of the stronger half of each bit, relative to full scale
+    pub signal_level: f32,
+    /// Difference between the two halves of each bit relative to their sum, from 0 to 255
+    pub confidence: [u8; LONG_FRAME_BITS],
+    /// How far after the first sample of the window the preamble starts
+    pub delay: Duration,
+}
+
+/// Ranks the phases of a candidate frame: a matching CRC wins, then the clearest bits
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+struct Score {
+    crc_ok: bool,
+    /// Average difference between the two halves of each bit, relative to their sum
+    margin: f32,
+}
+
+/// The samples a slot (a Mode S half bit or a Mode A/C pulse) overlaps, from `first`, weighted by how much of each
+/// sample it covers
+#[derive(Debug, Clone, Copy)]
+pub struct Slot {
+    first: usize,
+    len: usize,
+    weights: [u32; MAX_SLOT_SAMPLES],
+}
+
+impl Slot {
+    /// `len` samples from the fractional sample position `start`, with weights adding up to `total`
+    pub fn new(start: f32, len: f32, total: u32) -> Self {
+        let first = start as usize;
+        let end = start + len;
+        let mut slot = Slot {
+            first,
+            len: 0,
+            weights: [0; MAX_SLOT_SAMPLES],
+        };
+        while ((first + slot.len) as f32) < end {
+            let i = (first + slot.len) as f32;
+            let overlap = end.min(i + 1.0) - start.max(i);
+            slot.weights[slot.len] = (overlap * SAMPLE_WEIGHT).round() as u32;
+            slot.len += 1;
+        }
+
+        // Make every slot weigh the same despite rounding, so their levels can be compared directly
+        let sum = slot.weights.iter().sum::<u32>();
+        let heaviest = (0..slot.len).max_by_key(|&i| slot.weights[i]).unwrap();
+        slot.weights[heaviest] = (slot.weights[heaviest] + total).saturating_sub(sum);
+        slot
+    }
+
+    pub fn level(&self, m: &[u16]) -> u32 {
+        m[self.first..self.first + self.len]
+            .iter()
+            .zip(&self.weights)
+            .map(|(&sample, &weight)| sample as u32 * weight)
+            .sum()
+    }
+}
+
+/// The slots of a whole long frame, at one phase
+#[derive(Debug, Clone)]
+struct FrameSlots {
+    phase: f32,
+    preamble: [Slot; PREAMBLE_SLOTS],
+    /// The two halves of every bit
+    bits: [(Slot, Slot); LONG_FRAME_BITS],
+}
+
+#[derive(Clone)]
+pub struct Demodulator {
+    sample_rate: u32,
+    samples_per_us: f32,
+    min_snr_db: f32,
+    phases: Vec<FrameSlots>,
+    /// Level of a slot where every sample is at full scale
+    full_scale: f32,
+}
+
+impl Demodulator {
+    pub fn new(sample_rate: u32, min_snr_db: f32) -> Self {
+        let samples_per_us = sample_rate as f32 / 1e6;
+        let half_bit = samples_per_us / 2.0;
+        let total = (half_bit * SAMPLE_WEIGHT).round() as u32;
+        let phases = PHASES
+            .iter()
+            .map(|&phase| FrameSlots {
+                phase,
+                preamble: std::array::from_fn(|i| {
+                    Slot::new(phase + i as f32 * half_bit, half_bit, total)
+                }),
+                bits: std::array::from_fn(|i| {
+                    let start = phase + (PREAMBLE_US + i as f32) * samples_per_us;
+                    (
+                        Slot::new(start, half_bit, total),
+                        Slot::new(start + half_bit, half_bit, total),
+                    )
+                }),
+            })
+            .collect();
+
+        Demodulator {
+            sample_rate,
+            samples_per_us,
+            min_snr_db,
+            phases,
+            full_scale: total as f32 * magnitude::FULL_SCALE,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Number of samples from the start of the preamble to the end of a `bits` long frame, rounded down
+    pub fn frame_length(&self, bits: usize) -> usize {
+        ((PREAMBLE_US + bits as f32) * self.samples_per_us) as usize
+    }
+
+    /// Number of samples needed to demodulate a long frame at every phase
+    pub fn window(&self) -> usize {
+        ((PREAMBLE_US + LONG_FRAME_BITS as f32) * self.samples_per_us).ceil() as usize + 2
+    }
+
+    /// Demodulate a frame starting at the beginning of `m`, which must hold at least [`Demodulator::window`] samples.
+    ///
+    /// Every phase with a valid preamble is demodulated, and the one with the best score is kept.
+    pub fn demodulate(&self, m: &[u16]) -> Option<Demodulated> {
+        if m.len() < self.window() {
+            return None;
+        }
+
+        let mut best: Option<(Score, Demodulated)> = None;
+        for slots in &self.phases {
+            let levels = slots.preamble.map(|slot| slot.level(m));
+            let Some(preamble) = detect_preamble(&levels, self.min_snr_db, self.full_scale) else {
+                continue;
+            };
+
+            let mut data = [0u8; 14];
+            let halves = slots
+                .bits
+                .map(|(first, second)| (first.level(m), second.level(m)));
+            for (i, &(first, second)) in halves.iter().enumerate() {
+                if first > second {
+                    data[i / 8] |= 1 << (7 - i % 8);
+                }
+            }
+
+            let frame = Frame::new(&data, Timestamp::default(), 0.0);
+            let bits = frame.bits();
+            let margins = halves.map(|(a, b)| a.abs_diff(b) as f32 / (a + b).max(1) as f32);
+            let score = Score {
+                crc_ok: !decoder::has_address_parity(frame.downlink_format())
+                    && mode_s_checksum(&data, bits) == frame.parity(),
+                margin: margins[..bits].iter().sum::<f32>() / bits as f32,
+            };
+
+            if best.as_ref().is_none_or(|(best, _)| score > *best) {
+                let power = halves[..bits]
+                    .iter()
+                    .map(|&(a, b)| (a.max(b) as f32 / self.full_scale).powi(2))
+                    .sum::<f32>();
+                best = Some((
+                    score,
+                    Demodulated {
+                        data,
+                        preamble,
+                        signal_level: (power / bits as f32).min(1.0),
+                        confidence: margins.map(|margin| (margin * 255.0).round() as u8),
+                        delay: Duration::from_secs_f64(
+                            slots.phase as f64 / self.sample_rate as f64,
+                        ),
+                    },
+                ));
+            }
+        }
+
+        best.map(|(_, demodulated)| demodulated)
+    }
+}
+
+#[test]
+fn test_detect_preamble() {
+    let mut levels = [50; PREAMBLE_SLOTS];
+    for i in PREAMBLE_HIGH {
+        levels[i] = 500;
+    }
+
+    let preamble = detect_preamble(&levels, 6.0, 1000.0).unwrap();
+    assert_eq!(preamble.signal, 0.5);
+    assert_eq!(preamble.noise, 0.05);
+    assert!((preamble.snr_db() - 20.0).abs() < 0.01);
+
+    // Not enough signal
+    assert_eq!(detect_preamble(&levels, 25.0, 1000.0), None);
+
+    // A pulse in the quiet zone
+    levels[12] = 600;
+    assert_eq!(detect_preamble(&levels, 6.0, 1000.0), None);
+
+    // Noise
+    assert_eq!(detect_preamble(&[100; PREAMBLE_SLOTS], 0.0, 1000.0), None);
+}
+
+/// Integrate pulses, as `(start, end)` in µs, over `len` samples as the receiver would, at half of full scale.
+///
+/// The pulses are shifted `offset_us` into the buffer.
+#[cfg(test)]
+pub(crate) fn pulse_magnitudes(
+    pulses: &[(f32, f32)],
+    sample_rate: u32,
+    offset_us: f32,
+    len: usize,
+) -> Vec<u16> {
+    let samples_per_us = sample_rate as f32 / 1e6;
+    (0..len)
+        .map(|k| {
+            let start = k as f32 / samples_per_us - offset_us;
+            let end = start + 1.0 / samples_per_us;
+            let covered = pulses
+                .iter()
+                .map(|&(a, b)| (end.min(b) - start.max(a)).max(0.0))
+                .sum::<f32>();
+            (covered * samples_per_us * 0.5 * magnitude::FULL_SCALE) as u16
+        })
+        .collect()
+}
+
+/// The preamble and PPM pulses of the frame `data`, `offset_us` into a buffer long enough for a long frame.
+#[cfg(test)]
+pub(crate) fn frame_magnitudes(data: &[u8], sample_rate: u32, offset_us: f32) -> Vec<u16> {
+    let mut pulses = vec![(0.0, 0.5), (1.0, 1.5), (3.5, 4.0), (4.5, 5.0)];
+    for i in 0..data.len() * 8 {
+        let one = data[i / 8] & (1 << (7 - i % 8)) != 0;
+        let start = PREAMBLE_US + i as f32 + if one { 0.0 } else { 0.5 };
+        pulses.push((start, start + 0.5));
+    }
+    let len_us = offset_us + PREAMBLE_US + LONG_FRAME_BITS as f32;
+    let len = (len_us * sample_rate as f32 / 1e6).ceil() as usize + 2;
+    pulse_magnitudes(&pulses, sample_rate, offset_us, len)
+}
+
+#[test]
+fn test_demodulate() {
+    let data = [
+        0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+    ];
+
+    for (sample_rate, offset_us) in [(2_000_000, 0.0), (2_400_000, 0.25), (3_200_000, 0.1)] {
+        let demodulator = Demodulator::new(sample_rate, 6.0);
+        let m = frame_magnitudes(&data, sample_rate, offset_us);
+
+        let demodulated = demodulator.demodulate(&m).unwrap();
+        assert_eq!(demodulated.data, data, "at {sample_rate} S/s");
+        assert!(
+            (demodulated.delay.as_secs_f32() * 1e6 - offset_us).abs() < 0.1,
+            "at {sample_rate} S/s"
+        );
+        // Samples only partly covered by a pulse make it look weaker, unless they line up
+        if sample_rate == 2_000_000 {
+            assert!((demodulated.preamble.signal - 0.5).abs() < 0.001);
+        }
+    }
+}
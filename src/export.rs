@@ -0,0 +1,298 @@
+//! Export aircraft tracks to GPX, KML and GeoJSON
+
+use std::{fmt::Write, path::Path, time::SystemTime};
+
+use crate::{
+    aircraft::{Aircraft, TrackPoint},
+    timestamp::{Timestamp, UtcDateTime},
+};
+
+const FEET_TO_METERS: f64 = 0.3048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TrackFormat {
+    Gpx,
+    Kml,
+    #[value(name = "geojson")]
+    GeoJson,
+}
+
+impl TrackFormat {
+    /// Parse a file extension, eg. `gpx`
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "gpx" => Some(TrackFormat::Gpx),
+            "kml" => Some(TrackFormat::Kml),
+            "geojson" => Some(TrackFormat::GeoJson),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TrackFormat::Gpx => "gpx",
+            TrackFormat::Kml => "kml",
+            TrackFormat::GeoJson => "geojson",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            TrackFormat::Gpx => "application/gpx+xml",
+            TrackFormat::Kml => "application/vnd.google-earth.kml+xml",
+            TrackFormat::GeoJson => "application/geo+json",
+        }
+    }
+}
+
+/// Export the tracks of the given aircraft into a single document.
+///
+/// `start` is the wall clock time at timestamp 0, used to give every point its time.
+pub fn export_tracks<'a>(
+    aircraft: impl IntoIterator<Item = &'a Aircraft>,
+    format: TrackFormat,
+    start: SystemTime,
+) -> String {
+    let tracks = aircraft.into_iter().filter(|craft| !craft.path.is_empty());
+    let time = |point: &TrackPoint| {
+        UtcDateTime::from(start + point.time.saturating_duration_since(Timestamp::default()))
+    };
+    let altitude_m = |point: &TrackPoint| point.altitude.map(feet_to_meters);
+
+    match format {
+        TrackFormat::Gpx => {
+            let mut out = String::from(concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                "\n",
+                r#"<gpx version="1.1" creator="stribog" xmlns="http://www.topografix.com/GPX/1/1">"#,
+                "\n"
+            ));
+            for craft in tracks {
+                writeln!(
+                    out,
+                    "  <trk>\n    <name>{}</name>\n    <trkseg>",
+                    name(craft)
+                )
+                .unwrap();
+                for point in craft.path.iter() {
+                    write!(
+                        out,
+                        r#"      <trkpt lat="{}" lon="{}">"#,
+                        point.lat, point.lon
+                    )
+                    .unwrap();
+                    if let Some(altitude) = altitude_m(point) {
+                        write!(out, "<ele>{altitude:.1}</ele>").unwrap();
+                    }
+                    writeln!(out, "<time>{}</time></trkpt>", time(point)).unwrap();
+                }
+                out.push_str("    </trkseg>\n  </trk>\n");
+            }
+            out.push_str("</gpx>\n");
+            out
+        }
+        TrackFormat::Kml => {
+            let mut out = String::from(concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                "\n",
+                r#"<kml xmlns="http://www.opengis.net/kml/2.2" xmlns:gx="http://www.google.com/kml/ext/2.2">"#,
+                "\n<Document>\n"
+            ));
+            for craft in tracks {
+                // Extruded to the ground, so the altitude profile shows as a curtain below the track. Tracks without
+                // any altitude are drawn on the ground instead of at sea level
+                let altitudes = track_altitudes(craft);
+                let (extrude, altitude_mode) = match altitudes {
+                    Some(_) => (1, "absolute"),
+                    None => (0, "clampToGround"),
+                };
+                writeln!(
+                    out,
+                    "  <Placemark>\n    <name>{}</name>\n    <gx:Track>\n      <extrude>{extrude}</extrude>\n      <altitudeMode>{altitude_mode}</altitudeMode>",
+                    name(craft)
+                )
+                .unwrap();
+                for point in craft.path.iter() {
+                    writeln!(out, "      <when>{}</when>", time(point)).unwrap();
+                }
+                for (i, point) in craft.path.iter().enumerate() {
+                    let altitude = altitudes.as_ref().map_or(0.0, |altitudes| altitudes[i]);
+                    writeln!(
+                        out,
+                        "      <gx:coord>{} {} {altitude:.1}</gx:coord>",
+                        point.lon, point.lat
+                    )
+                    .unwrap();
+                }
+                out.push_str("    </gx:Track>\n  </Placemark>\n");
+            }
+            out.push_str("</Document>\n</kml>\n");
+            out
+        }
+        TrackFormat::GeoJson => {
+            let features = tracks
+                .map(|craft| {
+                    // Every position has an altitude, or none of them
+                    let altitudes = track_altitudes(craft);
+                    let mut coordinates = craft
+                        .path
+                        .iter()
+                        .enumerate()
+                        .map(|(i, point)| match &altitudes {
+                            Some(altitudes) => {
+                                serde_json::json!([point.lon, point.lat, altitudes[i]])
+                            }
+                            None => serde_json::json!([point.lon, point.lat]),
+                        })
+                        .collect::<Vec<_>>();
+                    let times = craft
+                        .path
+                        .iter()
+                        .map(|point| time(point).to_string())
+                        .collect::<Vec<_>>();
+
+                    // A line string needs at least two positions
+                    let geometry = if coordinates.len() == 1 {
+                        serde_json::json!({ "type": "Point", "coordinates": coordinates.remove(0) })
+                    } else {
+                        serde_json::json!({ "type": "LineString", "coordinates": coordinates })
+                    };
+
+                    serde_json::json!({
+                        "type": "Feature",
+                        "geometry": geometry,
+                        "properties": {
+                            "icao": craft.icao,
+                            "callsign": craft.callsign.map(|c| c.value.to_string()),
+                            "coordTimes": times,
+                        },
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            serde_json::json!({ "type": "FeatureCollection", "features": features }).to_string()
+        }
+    }
+}
+
+/// Write the track of an aircraft to `dir`, named after its address and the time it was first seen
+pub fn write_track(
+    dir: &Path,
+    craft: &Aircraft,
+    format: TrackFormat,
+    start: SystemTime,
+) -> anyhow::Result<()> {
+    let first_seen = UtcDateTime::from(
+        start
+            + craft
+                .first_seen
+                .saturating_duration_since(Timestamp::default()),
+    );
+    let file_name = format!(
+        "{}_{:04}{:02}{:02}T{:02}{:02}{:02}Z.{}",
+        craft.icao,
+        first_seen.year,
+        first_seen.month,
+        first_seen.day,
+        first_seen.hour,
+        first_seen.minute,
+        first_seen.second,
+        format.extension()
+    );
+
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join(file_name), export_tracks([craft], format, start))?;
+    Ok(())
+}
+
+fn feet_to_meters(feet: i32) -> f64 {
+    (feet as f64 * FEET_TO_METERS * 10.0).round() / 10.0
+}
+
+/// The altitude of every position of a track in meters, or `None` if none is known. Positions without an altitude get
+/// the last one known, or the first one for positions before it
+fn track_altitudes(craft: &Aircraft) -> Option<Vec<f64>> {
+    let first = craft.path.iter().find_map(|point| point.altitude)?;
+    let mut last = first;
+    Some(
+        craft
+            .path
+            .iter()
+            .map(|point| {
+                last = point.altitude.unwrap_or(last);
+                feet_to_meters(last)
+            })
+            .collect(),
+    )
+}
+
+/// Track name: the address, followed by the callsign if known
+fn name(craft: &Aircraft) -> String {
+    match craft.callsign {
+        Some(callsign) => format!("{} {}", craft.icao, callsign.value),
+        None => craft.icao.to_string(),
+    }
+}
+
+#[test]
+fn test_export_tracks() {
+    use crate::{
+        aircraft::{DataSource, Icao, Tracked},
+        altitude::{Altitude, AltitudeKind},
+    };
+
+    let start = SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(1_227_916_098_611);
+    let mut craft = Aircraft::new(Icao::new(0x4840D6), Timestamp::default());
+    craft.altitude_baro = Some(Tracked::new(
+        Altitude::Feet(AltitudeKind::Barometric, 1000),
+        Timestamp::default(),
+        DataSource::AdsB,
+    ));
+    craft.set_position((52.25, 3.91), Timestamp::default(), DataSource::AdsB);
+
+    let gpx = export_tracks([&craft], TrackFormat::Gpx, start);
+    assert!(gpx.contains(
+        r#"<trkpt lat="52.25" lon="3.91"><ele>304.8</ele><time>2008-11-28T23:48:18.611Z</time></trkpt>"#
+    ));
+
+    let kml = export_tracks([&craft], TrackFormat::Kml, start);
+    assert!(kml.contains("<gx:coord>3.91 52.25 304.8</gx:coord>"));
+
+    let geojson = |craft: &Aircraft| -> serde_json::Value {
+        serde_json::from_str(&export_tracks([craft], TrackFormat::GeoJson, start)).unwrap()
+    };
+    let feature = &geojson(&craft)["features"][0];
+    assert_eq!(
+        feature["geometry"],
+        serde_json::json!({ "type": "Point", "coordinates": [3.91, 52.25, 304.8] })
+    );
+    assert_eq!(feature["properties"]["icao"], "4840D6");
+    assert_eq!(
+        feature["properties"]["coordTimes"][0],
+        "2008-11-28T23:48:18.611Z"
+    );
+
+    // A position without altitude keeps the previous one
+    craft.altitude_baro = None;
+    craft.set_position((52.26, 3.92), Timestamp::default(), DataSource::AdsB);
+    let kml = export_tracks([&craft], TrackFormat::Kml, start);
+    assert!(kml.contains("<gx:coord>3.92 52.26 304.8</gx:coord>"));
+    assert_eq!(
+        geojson(&craft)["features"][0]["geometry"],
+        serde_json::json!({
+            "type": "LineString",
+            "coordinates": [[3.91, 52.25, 304.8], [3.92, 52.26, 304.8]],
+        })
+    );
+
+    // A track without any altitude stays on the ground
+    let mut craft = Aircraft::new(Icao::new(0x4840D6), Timestamp::default());
+    craft.set_position((52.25, 3.91), Timestamp::default(), DataSource::AdsB);
+    craft.set_position((52.26, 3.92), Timestamp::default(), DataSource::AdsB);
+    let kml = export_tracks([&craft], TrackFormat::Kml, start);
+    assert!(kml.contains("<altitudeMode>clampToGround</altitudeMode>"));
+    assert_eq!(
+        geojson(&craft)["features"][0]["geometry"]["coordinates"],
+        serde_json::json!([[3.91, 52.25], [3.92, 52.26]])
+    );
+}
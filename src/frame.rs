@@ -0,0 +1,133 @@
+use serde::Serialize;
+
+use crate::{
+    aircraft::Icao,
+    crc::{self, mode_s_checksum},
+    decoder::has_address_parity,
+    timestamp::Timestamp,
+};
+
+/// Identifies the receiver (SDR, network or file input) a frame came from, an index into [`crate::stats::Stats::sources`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct SourceId(pub u16);
+
+/// A demodulated Mode S frame
+#[derive(Debug, Clone)]
+pub struct Frame {
+    data: [u8; 14],
+    pub timestamp: Timestamp,
+    pub source: SourceId,
+    /// Mean signal power of the frame relative to full scale (0.0-1.0)
+    pub signal_level: f32,
+    /// Number of bits that were flipped by error correction
+    pub corrected_bits: u8,
+    /// How sure the demodulator was of each bit, from 0 (a coin toss) to 255. Only known for frames demodulated here
+    pub confidence: Option<[u8; 112]>,
+    /// Noise floor of the samples around the frame, as a power relative to full scale. Only known for frames
+    /// demodulated here, or replayed from their recording
+    pub noise_floor: Option<f32>,
+}
+
+impl Frame {
+    /// Build a frame from demodulated bytes. The length is derived from the downlink format,
+    /// any bytes past the end of the frame are ignored
+    pub fn new(bytes: &[u8], timestamp: Timestamp, signal_level: f32) -> Self {
+        let mut data = [0u8; 14];
+        let len = bytes.len().min(14);
+        data[..len].copy_from_slice(&bytes[..len]);
+
+        Frame {
+            data,
+            timestamp,
+            source: SourceId::default(),
+            signal_level,
+            corrected_bits: 0,
+            confidence: None,
+            noise_floor: None,
+        }
+    }
+
+    /// Signal strength in dBFS
+    pub fn rssi_dbfs(&self) -> f64 {
+        10.0 * (self.signal_level as f64 + 1e-5).log10()
+    }
+
+    /// How far the frame stood above the noise floor, in dB
+    pub fn snr_db(&self) -> Option<f64> {
+        self.noise_floor
+            .map(|noise| self.rssi_dbfs() - 10.0 * (noise as f64 + 1e-5).log10())
+    }
+
+    pub fn downlink_format(&self) -> u8 {
+        self.data[0] >> 3
+    }
+
+    /// Frame length in bits, either 56 or 112
+    pub fn bits(&self) -> usize {
+        if self.downlink_format() <= 11 {
+            56
+        } else {
+            112
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.bits() / 8]
+    }
+
+    /// Compare the CRC with the parity field, flipping bits if that makes them match.
+    ///
+    /// Extended squitters (DF 17/18) may have up to two bits corrected and all-call replies (DF 11) one, other formats
+    /// none. Returns false if the frame is damaged beyond repair. Only applies to frames without address/parity
+    pub fn fix_parity(&mut self) -> bool {
+        let bits = self.bits();
+        let syndrome = crc::syndrome(&self.data, bits);
+        if syndrome == 0 {
+            return true;
+        }
+
+        let max_errors = match self.downlink_format() {
+            // Replies to a specific interrogator carry its code in the low 7 bits of the parity
+            11 if syndrome < 0x80 => return true,
+            11 => 1,
+            17 | 18 => 2,
+            _ => 0,
+        };
+
+        let confidence = self.confidence.as_ref().map(|c| &c[..bits]);
+        match crc::fix_errors(&mut self.data, bits, max_errors, confidence) {
+            Some(correction) => {
+                debug!("Fixed bits {:?}", correction.bits().collect::<Vec<_>>());
+                self.corrected_bits = correction.count();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The aircraft address, from the address field (DF 11/17/18) or recovered from the address/parity field.
+    ///
+    /// Addresses recovered from address/parity are only correct if the frame was received without errors.
+    pub fn icao(&self) -> Option<Icao> {
+        let downlink_format = self.downlink_format();
+        if matches!(downlink_format, 11 | 17 | 18) {
+            let data = &self.data;
+            Some(Icao::new(
+                (data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32,
+            ))
+        } else if has_address_parity(downlink_format) {
+            Some(Icao::new(
+                mode_s_checksum(&self.data, self.bits()) ^ self.parity(),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// The 24-bit parity (or address/parity) field at the end of the frame
+    pub fn parity(&self) -> u32 {
+        let data = self.data();
+        let end = data.len();
+        (data[end - 3] as u32) << 16 | (data[end - 2] as u32) << 8 | data[end - 1] as u32
+    }
+}
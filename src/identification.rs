@@ -0,0 +1,164 @@
+use std::fmt::{Debug, Display};
+
+/// 6-bit character set used by aircraft identification messages. `#` marks illegal characters
+const AIS_LOOKUP: &[u8; 64] = b"#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######";
+
+/// An 8 character aircraft identification (flight number or registration), padded with trailing spaces
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Callsign([u8; 8]);
+
+impl Callsign {
+    /// Decode a callsign from its eight 6-bit AIS character codes.
+    ///
+    /// Returns `None` if any of the characters is outside of the AIS character set, or if the callsign is blank.
+    pub fn from_ais(codes: [u8; 8]) -> Option<Self> {
+        let mut chars = [b' '; 8];
+        for (c, code) in chars.iter_mut().zip(codes) {
+            *c = *AIS_LOOKUP.get(code as usize)?;
+            if *c == b'#' {
+                return None;
+            }
+        }
+
+        if chars.iter().all(|&c| c == b' ') {
+            return None;
+        }
+
+        Some(Callsign(chars))
+    }
+
+    /// Parse a callsign from text, eg. from an SBS stream.
+    ///
+    /// Returns `None` if it is blank, longer than 8 characters or contains characters outside of the AIS character set.
+    pub fn new(text: &str) -> Option<Self> {
+        let text = text.trim_end();
+        if text.is_empty() || text.len() > 8 {
+            return None;
+        }
+
+        let mut chars = [b' '; 8];
+        for (c, b) in chars.iter_mut().zip(text.bytes()) {
+            if b == b'#' || !AIS_LOOKUP.contains(&b) {
+                return None;
+            }
+            *c = b;
+        }
+
+        Some(Callsign(chars))
+    }
+
+    /// The callsign without trailing padding
+    pub fn as_str(&self) -> &str {
+        // Only characters from the AIS lookup table can end up in here
+        std::str::from_utf8(&self.0).unwrap().trim_end()
+    }
+}
+
+impl Display for Callsign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Callsign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Callsign({:?})", self.as_str())
+    }
+}
+
+/// Emitter category transmitted alongside the callsign, derived from the type code (1-4) and the 3-bit category field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WakeVortexCategory {
+    NoInformation,
+    Reserved,
+
+    SurfaceEmergencyVehicle,
+    SurfaceServiceVehicle,
+    GroundObstruction,
+
+    Glider,
+    LighterThanAir,
+    Parachutist,
+    Ultralight,
+    UnmannedAerialVehicle,
+    SpaceVehicle,
+
+    Light,
+    Medium1,
+    Medium2,
+    HighVortex,
+    Heavy,
+    HighPerformance,
+    Rotorcraft,
+}
+
+impl WakeVortexCategory {
+    pub fn new(type_code: u32, category: u32) -> Self {
+        match (type_code, category) {
+            (1, _) => Self::Reserved,
+            (_, 0) => Self::NoInformation,
+            (2, 1) => Self::SurfaceEmergencyVehicle,
+            (2, 3) => Self::SurfaceServiceVehicle,
+            (2, 4..=7) => Self::GroundObstruction,
+            (3, 1) => Self::Glider,
+            (3, 2) => Self::LighterThanAir,
+            (3, 3) => Self::Parachutist,
+            (3, 4) => Self::Ultralight,
+            (3, 6) => Self::UnmannedAerialVehicle,
+            (3, 7) => Self::SpaceVehicle,
+            (4, 1) => Self::Light,
+            (4, 2) => Self::Medium1,
+            (4, 3) => Self::Medium2,
+            (4, 4) => Self::HighVortex,
+            (4, 5) => Self::Heavy,
+            (4, 6) => Self::HighPerformance,
+            (4, 7) => Self::Rotorcraft,
+            _ => Self::Reserved,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::NoInformation => "No category information",
+            Self::Reserved => "Reserved",
+            Self::SurfaceEmergencyVehicle => "Surface emergency vehicle",
+            Self::SurfaceServiceVehicle => "Surface service vehicle",
+            Self::GroundObstruction => "Ground obstruction",
+            Self::Glider => "Glider, sailplane",
+            Self::LighterThanAir => "Lighter-than-air",
+            Self::Parachutist => "Parachutist, skydiver",
+            Self::Ultralight => "Ultralight, hang-glider, paraglider",
+            Self::UnmannedAerialVehicle => "Unmanned aerial vehicle",
+            Self::SpaceVehicle => "Space or transatmospheric vehicle",
+            Self::Light => "Light (less than 7000 kg)",
+            Self::Medium1 => "Medium 1 (between 7000 kg and 34000 kg)",
+            Self::Medium2 => "Medium 2 (between 34000 kg to 136000 kg)",
+            Self::HighVortex => "High vortex aircraft",
+            Self::Heavy => "Heavy (larger than 136000 kg)",
+            Self::HighPerformance => {
+                "High performance (>5 g acceleration) and high speed (>400 kt)"
+            }
+            Self::Rotorcraft => "Rotorcraft",
+        }
+    }
+}
+
+impl Display for WakeVortexCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+#[test]
+fn test_callsign() {
+    // KLM1023_
+    let codes = [11, 12, 13, 49, 48, 50, 51, 32];
+    let callsign = Callsign::from_ais(codes).unwrap();
+    assert_eq!(callsign.as_str(), "KLM1023");
+    assert_eq!(Callsign::new("KLM1023 "), Some(callsign));
+    assert_eq!(Callsign::new("klm1023"), None);
+
+    assert_eq!(Callsign::from_ais([32; 8]), None);
+    assert_eq!(Callsign::from_ais([11, 12, 13, 0, 32, 32, 32, 32]), None);
+    assert_eq!(Callsign::from_ais([11, 12, 13, 40, 32, 32, 32, 32]), None);
+}
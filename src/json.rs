@@ -0,0 +1,185 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{aircraft::Aircraft, stats::Stats, timestamp::Timestamp, tracker::Tracker};
+
+/// Contents of `aircraft.json`, in the format used by dump1090 and readsb
+#[derive(Serialize)]
+pub struct AircraftFile {
+    /// Unix time this snapshot was taken at
+    pub now: f64,
+    /// Total number of messages received
+    pub messages: u64,
+    pub aircraft: Vec<AircraftEntry>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum BarometricAltitude {
+    Feet(i32),
+    /// Always "ground"
+    Ground(&'static str),
+}
+
+#[derive(Serialize)]
+pub struct AircraftEntry {
+    pub hex: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flight: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_baro: Option<BarometricAltitude>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_geom: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub squawk: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lon: Option<f64>,
+    /// Seconds since the last position update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seen_pos: Option<f64>,
+    /// Seconds since the last message
+    pub seen: f64,
+    pub messages: u64,
+    pub rssi: f64,
+}
+
+impl AircraftEntry {
+    pub fn new(craft: &Aircraft, now: Timestamp) -> Self {
+        let seconds_since = |t: Timestamp| now.saturating_duration_since(t).as_secs_f64();
+        let on_ground = craft.on_ground.is_some_and(|g| g.value);
+
+        AircraftEntry {
+            hex: craft.icao.to_string().to_lowercase(),
+            flight: craft.callsign.map(|c| c.value.to_string()),
+            alt_baro: if on_ground {
+                Some(BarometricAltitude::Ground("ground"))
+            } else {
+                craft
+                    .altitude_baro
+                    .and_then(|a| a.value.feet())
+                    .map(BarometricAltitude::Feet)
+            },
+            alt_geom: craft.altitude_geom.and_then(|a| a.value.feet()),
+            gs: craft.speed_kts().map(|s| (s * 10.0).round() / 10.0),
+            track: craft.heading().map(|h| (h * 10.0).round() / 10.0),
+            squawk: craft.squawk.map(|s| format!("{:04X}", s.value)),
+            lat: craft.latlong().map(|(lat, _)| lat),
+            lon: craft.latlong().map(|(_, lon)| lon),
+            seen_pos: craft.position.map(|p| seconds_since(p.updated)),
+            seen: seconds_since(craft.last_seen),
+            messages: craft.messages.total,
+            rssi: (craft.rssi() * 10.0).round() / 10.0,
+        }
+    }
+}
+
+impl AircraftFile {
+    pub fn new(tracker: &Tracker, stats: &Stats, now: Timestamp, wall_clock: SystemTime) -> Self {
+        AircraftFile {
+            now: unix_time(wall_clock),
+            messages: stats.messages,
+            aircraft: tracker
+                .aircrafts
+                .values()
+                .map(|craft| AircraftEntry::new(craft, now))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ReceiverFile {
+    version: &'static str,
+    /// How often aircraft.json is updated, in milliseconds
+    refresh: u128,
+    history: u32,
+}
+
+#[derive(Serialize)]
+struct StatsFile<'a> {
+    now: f64,
+    total: &'a Stats,
+}
+
+/// Periodically writes `aircraft.json`, `receiver.json` and `stats.json` to a directory
+pub struct JsonWriter {
+    dir: PathBuf,
+    interval: Duration,
+    last_write: Option<Timestamp>,
+}
+
+impl JsonWriter {
+    pub fn new(dir: impl Into<PathBuf>, interval: Duration) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        write_atomic(
+            &dir.join("receiver.json"),
+            &ReceiverFile {
+                version: env!("CARGO_PKG_VERSION"),
+                refresh: interval.as_millis(),
+                history: 0,
+            },
+        )?;
+
+        Ok(JsonWriter {
+            dir,
+            interval,
+            last_write: None,
+        })
+    }
+
+    /// Write new snapshots if the update interval has passed
+    pub fn update(
+        &mut self,
+        tracker: &Tracker,
+        stats: &Stats,
+        now: Timestamp,
+        wall_clock: SystemTime,
+    ) -> anyhow::Result<()> {
+        if self
+            .last_write
+            .is_some_and(|t| now.saturating_duration_since(t) < self.interval)
+        {
+            return Ok(());
+        }
+        self.last_write = Some(now);
+
+        write_atomic(
+            &self.dir.join("aircraft.json"),
+            &AircraftFile::new(tracker, stats, now, wall_clock),
+        )?;
+        write_atomic(
+            &self.dir.join("stats.json"),
+            &StatsFile {
+                now: unix_time(wall_clock),
+                total: stats,
+            },
+        )?;
+
+        Ok(())
+    }
+}
+
+pub fn unix_time(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Write to a temporary file first and rename it over the target, so readers never see a partially written file
+fn write_atomic(path: &Path, value: &impl Serialize) -> anyhow::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(value)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,303 @@
+//! Conversion of IQ samples to 16 bit magnitudes, where [`FULL_SCALE`] is a full scale I or Q on its own.
+//!
+//! The DC offset of I and Q is tracked from block to block and removed before taking magnitudes, so a dongle whose
+//! center isn't exactly at 127.5 doesn't raise the noise floor.
+
+use std::{f32::consts::SQRT_2, sync::Arc};
+
+/// Magnitude of a full scale I or Q. The corners of the IQ plane (√2) still fit in 16 bits
+pub const FULL_SCALE: f32 = 65535.0 / SQRT_2;
+
+/// Weight of each new block in the running DC offset
+const DC_SMOOTHING: f32 = 0.2;
+
+/// The `cu8` table is rebuilt once the DC offset has moved by a tenth of a step
+const CU8_TABLE_TOLERANCE: f32 = 0.1 / 127.5;
+
+/// Samples per chunk when measuring the noise floor
+const NOISE_CHUNK: usize = 1024;
+
+/// Layout of raw IQ samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum IqFormat {
+    /// Unsigned 8 bit I and Q, centered on 127.5 (RTL-SDR)
+    Cu8,
+    /// Signed 16 bit little endian I and Q
+    Cs16,
+}
+
+impl IqFormat {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            IqFormat::Cu8 => 2,
+            IqFormat::Cs16 => 4,
+        }
+    }
+
+    /// Mean I and Q of `iq`, relative to full scale
+    fn mean(self, iq: &[u8]) -> [f32; 2] {
+        let samples = (iq.len() / self.bytes_per_sample()).max(1) as f64;
+        match self {
+            IqFormat::Cu8 => {
+                let mut sums = [0u64; 2];
+                for sample in iq.chunks_exact(2) {
+                    sums[0] += sample[0] as u64;
+                    sums[1] += sample[1] as u64;
+                }
+                sums.map(|sum| ((sum as f64 / samples - 127.5) / 127.5) as f32)
+            }
+            IqFormat::Cs16 => {
+                let mut sums = [0i64; 2];
+                for sample in iq.chunks_exact(4) {
+                    sums[0] += i16::from_le_bytes([sample[0], sample[1]]) as i64;
+                    sums[1] += i16::from_le_bytes([sample[2], sample[3]]) as i64;
+                }
+                sums.map(|sum| (sum as f64 / samples / 32768.0) as f32)
+            }
+        }
+    }
+}
+
+/// Tracks the DC offset of consecutive blocks of IQ samples, giving the [`Conversion`] that removes it from each.
+///
+/// It must see every block in order, so it lives with the reader rather than with the demodulators
+pub struct MagnitudeConverter {
+    format: IqFormat,
+    /// Running I and Q DC offsets, relative to full scale
+    dc_offset: Option<[f32; 2]>,
+    /// Table for [`cu8`], and the offset it was built for
+    cu8_table: Option<([f32; 2], Arc<[u16]>)>,
+}
+
+impl MagnitudeConverter {
+    pub fn new(format: IqFormat) -> Self {
+        MagnitudeConverter {
+            format,
+            dc_offset: None,
+            cu8_table: None,
+        }
+    }
+
+    /// Update the DC offset with the mean of `iq`, returning the conversion for this block
+    pub fn update(&mut self, iq: &[u8]) -> Conversion {
+        let mean = self.format.mean(iq);
+        let dc_offset = match self.dc_offset {
+            Some(dc) => [0, 1].map(|k| dc[k] + (mean[k] - dc[k]) * DC_SMOOTHING),
+            None => mean,
+        };
+        self.dc_offset = Some(dc_offset);
+
+        let cu8_table = match self.format {
+            IqFormat::Cu8 => {
+                let stale = self.cu8_table.as_ref().is_none_or(|(built, _)| {
+                    (0..2).any(|k| (built[k] - dc_offset[k]).abs() > CU8_TABLE_TOLERANCE)
+                });
+                if stale {
+                    self.cu8_table = Some((dc_offset, cu8_table(dc_offset).into()));
+                }
+                self.cu8_table.as_ref().map(|(_, table)| table.clone())
+            }
+            IqFormat::Cs16 => None,
+        };
+
+        Conversion {
+            dc_offset,
+            cu8_table,
+        }
+    }
+}
+
+/// How to turn one block of IQ samples into magnitudes, removing the DC offset measured up to it
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    /// I and Q DC offsets, relative to full scale
+    pub dc_offset: [f32; 2],
+    /// The [`cu8_table`] for `dc_offset`, shared between blocks. `None` for `cs16` samples
+    cu8_table: Option<Arc<[u16]>>,
+}
+
+impl Conversion {
+    /// Append the magnitudes of `iq` to `out`
+    pub fn convert(&self, iq: &[u8], out: &mut Vec<u16>) {
+        match &self.cu8_table {
+            Some(table) => cu8(iq, table, out),
+            None => cs16(iq, self.dc_offset, out),
+        }
+    }
+}
+
+/// Magnitude of every unsigned 8 bit (I, Q) pair once `dc_offset` is removed, indexed by `I << 8 | Q`
+pub fn cu8_table(dc_offset: [f32; 2]) -> Box<[u16]> {
+    (0..=u16::MAX)
+        .map(|iq| {
+            let i = ((iq >> 8) as f32 - 127.5) / 127.5 - dc_offset[0];
+            let q = ((iq & 0xFF) as f32 - 127.5) / 127.5 - dc_offset[1];
+            ((i * i + q * q).sqrt() * FULL_SCALE).round().min(65535.0) as u16
+        })
+        .collect()
+}
+
+/// Append the magnitudes of unsigned 8 bit IQ samples (RTL-SDR), by lookup in a [`cu8_table`]
+pub fn cu8(iq: &[u8], table: &[u16], out: &mut Vec<u16>) {
+    out.extend(
+        iq.chunks_exact(2)
+            .map(|c| table[(c[0] as usize) << 8 | c[1] as usize]),
+    );
+}
+
+/// Full scale I or Q of a `cs16` sample, in magnitude units
+const CS16_SCALE: f32 = FULL_SCALE / 32768.0;
+
+/// Append the magnitudes of signed 16 bit little endian IQ samples, once `dc_offset` is removed.
+///
+/// Eight samples at a time with SSE2 on x86-64, which every x86-64 CPU has, and one at a time elsewhere
+pub fn cs16(iq: &[u8], dc_offset: [f32; 2], out: &mut Vec<u16>) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let simd = iq.len() / 32 * 32;
+        // SAFETY: SSE2 is part of x86-64
+        unsafe { cs16_sse2(&iq[..simd], dc_offset, out) };
+        cs16_scalar(&iq[simd..], dc_offset, out);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    cs16_scalar(iq, dc_offset, out);
+}
+
+/// [`cs16`] one sample at a time.
+///
+/// Rounded by adding a half rather than with `round`, which isn't a single instruction on baseline x86-64. The
+/// conversion saturates
+pub fn cs16_scalar(iq: &[u8], dc_offset: [f32; 2], out: &mut Vec<u16>) {
+    let [dc_i, dc_q] = dc_offset.map(|dc| dc * 32768.0);
+    out.extend(iq.chunks_exact(4).map(|sample| {
+        let i = i16::from_le_bytes([sample[0], sample[1]]) as f32 - dc_i;
+        let q = i16::from_le_bytes([sample[2], sample[3]]) as f32 - dc_q;
+        ((i * i + q * q).sqrt() * CS16_SCALE + 0.5) as u16
+    }));
+}
+
+/// [`cs16`] for a multiple of 8 samples, with the same arithmetic as [`cs16_scalar`] so the results are identical
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+fn cs16_sse2(iq: &[u8], dc_offset: [f32; 2], out: &mut Vec<u16>) {
+    use std::arch::x86_64::*;
+
+    let [dc_i, dc_q] = dc_offset.map(|dc| _mm_set1_ps(dc * 32768.0));
+    let scale = _mm_set1_ps(CS16_SCALE);
+    let half = _mm_set1_ps(0.5);
+    let max = _mm_set1_ps(65535.0);
+
+    // Four samples, each an I16 in the low half of a 32 bit lane and a Q16 in the high half, to u16 magnitudes
+    // offset by -32768 in i32 lanes, ready to be packed with signed saturation
+    let magnitudes = |iq: __m128i| {
+        let i = _mm_sub_ps(
+            _mm_cvtepi32_ps(_mm_srai_epi32(_mm_slli_epi32(iq, 16), 16)),
+            dc_i,
+        );
+        let q = _mm_sub_ps(_mm_cvtepi32_ps(_mm_srai_epi32(iq, 16)), dc_q);
+        let magnitude = _mm_sqrt_ps(_mm_add_ps(_mm_mul_ps(i, i), _mm_mul_ps(q, q)));
+        let magnitude = _mm_min_ps(_mm_add_ps(_mm_mul_ps(magnitude, scale), half), max);
+        _mm_sub_epi32(_mm_cvttps_epi32(magnitude), _mm_set1_epi32(32768))
+    };
+
+    out.reserve(iq.len() / 4);
+    for chunk in iq.chunks_exact(32) {
+        let mut m = [0u16; 8];
+        // SAFETY: the chunk is 32 bytes long and `m` 16, unaligned loads and stores are used
+        unsafe {
+            let low = _mm_loadu_si128(chunk.as_ptr().cast());
+            let high = _mm_loadu_si128(chunk.as_ptr().add(16).cast());
+            let packed = _mm_packs_epi32(magnitudes(low), magnitudes(high));
+            _mm_storeu_si128(
+                m.as_mut_ptr().cast(),
+                _mm_xor_si128(packed, _mm_set1_epi16(i16::MIN)),
+            );
+        }
+        out.extend(m);
+    }
+}
+
+/// Noise floor of a block of magnitudes, as a power relative to full scale.
+///
+/// The block is split in chunks of [`NOISE_CHUNK`] samples and the median of their mean powers is taken, so the
+/// chunks that contain frames are left out unless the channel is busier than it is quiet.
+pub fn noise_floor(m: &[u16]) -> f32 {
+    let mut powers = m
+        .chunks(NOISE_CHUNK)
+        .map(|chunk| {
+            chunk.iter().map(|&s| (s as u64).pow(2)).sum::<u64>() as f64 / chunk.len() as f64
+        })
+        .collect::<Vec<_>>();
+    if powers.is_empty() {
+        return 0.0;
+    }
+
+    let middle = powers.len() / 2;
+    let (_, median, _) = powers.select_nth_unstable_by(middle, f64::total_cmp);
+    (*median / (FULL_SCALE as f64).powi(2)) as f32
+}
+
+#[test]
+fn test_magnitudes() {
+    let mut out = Vec::new();
+    cu8(&[255, 127, 0, 0, 128, 128], &cu8_table([0.0; 2]), &mut out);
+    assert_eq!(out[0], 46341); // (1, -0.004)
+    assert_eq!(out[1], 65535); // (-1, -1)
+    assert_eq!(out[2], 257); // (0.004, 0.004)
+
+    // Enough samples for a full chunk and a remainder
+    let iq = [[i16::MAX, 0], [0, i16::MIN], [0, 0]]
+        .repeat(3)
+        .concat()
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<_>>();
+    out.clear();
+    cs16(&iq, [0.0; 2], &mut out);
+    assert_eq!(out.len(), 9);
+    assert_eq!(&out[..3], &[46339, 46340, 0]);
+    assert_eq!(&out[6..], &[46339, 46340, 0]);
+
+    // The SIMD conversion matches the scalar one, including in the saturated corners
+    let mut state = 0x2545_f491_u32;
+    // Corners first, so they go through the SIMD path
+    let mut iq = [
+        [i16::MIN, i16::MIN],
+        [i16::MAX, i16::MIN],
+        [i16::MIN, i16::MAX],
+    ]
+    .concat()
+    .iter()
+    .flat_map(|v| v.to_le_bytes())
+    .collect::<Vec<_>>();
+    iq.extend((0..4 * 1000).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    }));
+    for dc_offset in [[0.0; 2], [0.01, -0.02], [-0.01, 0.02]] {
+        let mut scalar = Vec::new();
+        out.clear();
+        cs16(&iq, dc_offset, &mut out);
+        cs16_scalar(&iq, dc_offset, &mut scalar);
+        assert_eq!(out, scalar);
+    }
+}
+
+#[test]
+fn test_dc_offset() {
+    // A dongle centered on (130, 125) instead of 127.5
+    let mut converter = MagnitudeConverter::new(IqFormat::Cu8);
+    let mut out = Vec::new();
+    let iq = [130, 125].repeat(4096);
+    let conversion = converter.update(&iq);
+    conversion.convert(&iq, &mut out);
+    assert!((conversion.dc_offset[0] - 2.5 / 127.5).abs() < 1e-6);
+    assert!(out.iter().all(|&m| m == 0));
+
+    // Noise at a quarter of full scale power, with a loud frame in one chunk
+    let mut m = vec![(FULL_SCALE / 2.0) as u16; 4 * NOISE_CHUNK];
+    m[..100].fill(u16::MAX);
+    assert!((noise_floor(&m) - 0.25).abs() < 0.001);
+}
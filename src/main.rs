@@ -1,591 +1,204 @@
-use glam::DVec2;
-use rtlsdr_rs::{RtlSdr, TunerGain};
+use clap::Parser;
 use std::{
-    borrow::Cow,
-    collections::HashMap,
-    io::{BufWriter, Write},
-    time::Instant,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::{
-    aircraft::{Aircraft, Altitude, Icao},
-    bitreader::BitReader,
+    args::Args,
+    demod::Demodulator,
+    frame::SourceId,
+    mode_ac::ModeAcDemodulator,
+    net::input::{self, Input},
+    pipeline::Pipeline,
+    recording::FrameLog,
+    timestamp::SampleClock,
 };
 mod aircraft;
+mod altitude;
+mod args;
 mod bitreader;
 mod cpr;
-
-const DEFAULT_BUF_LENGTH: usize = 16 * 16384;
-// const DEFAULT_BUF_LENGTH: usize = 2_500_000;
-
-const DEVICE_INDEX: usize = 0;
-const SAMPLE_RATE: u32 = 2_000_000;
+mod crc;
+mod decoder;
+mod dedup;
+mod demod;
+mod export;
+mod frame;
+mod identification;
+mod json;
+mod magnitude;
+mod mode_ac;
+mod net;
+mod pipeline;
+mod recording;
+mod sdr;
+mod stats;
+mod timestamp;
+mod tracker;
 
 #[macro_use]
 extern crate log;
 
 fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
     env_logger::builder()
         .filter_level(log::LevelFilter::Info)
         .format_timestamp(None)
         .init();
 
-    // Open device
-    let mut sdr = RtlSdr::open(DEVICE_INDEX).expect("Unable to open SDR device!");
-    // info!("{:#?}", sdr);
+    // Replays run on the clock of the original recording
+    let replay = args.replay.as_ref().map(FrameLog::open).transpose()?;
+    let start = match &replay {
+        Some(log) => log.start,
+        None => Some(SystemTime::now()),
+    };
 
-    let gains = sdr.get_tuner_gains()?;
-    info!(
-        "Supported gain values ({}): {:?}",
-        gains.len(),
-        gains,
-        // gains
-        //     .iter()
-        //     .map(|g| { *g as f32 / 10.0 })
-        //     .collect::<Vec<_>>()
+    anyhow::ensure!(
+        (2_000_000..=demod::MAX_SAMPLE_RATE).contains(&args.sample_rate),
+        "the sample rate must be between 2 and 20 MS/s"
     );
-
-    // sdr.set_direct_sampling(rtlsdr_rs::DirectSampleMode::On)?;
-    // Set sample rate
-    sdr.set_sample_rate(SAMPLE_RATE)?;
-    info!("Sampling at {} S/s", sdr.get_sample_rate());
-
-    // sdr.set_tuner_bandwidth(3_000_000)?;
-    sdr.set_bias_tee(false)?;
-    // sdr.set_tuner_gain(TunerGain::Manual(328))?;
-    sdr.set_tuner_gain(TunerGain::Manual(496))?;
-    // sdr.set_tuner_gain(TunerGain::Auto)?;
-
-    // sdr.set_center_freq(1_030_000_000)?; // Set center frequency to 1.090 GHz
-    sdr.set_freq_correction(0)?;
-    sdr.set_center_freq(1_090_000_000)?; // Set center frequency to 1.090 GHz
-
-    // Reset the endpoint before we try to read from it (mandatory)
-    info!("Reset buffer");
-    sdr.reset_buffer()?;
-
-    let mut bitmap =
-        BufWriter::new(std::fs::File::create("bitmap.data").expect("Unable to create dump file"));
-
-    info!("Reading samples in sync mode...");
-    let mut buf = vec![0u8; DEFAULT_BUF_LENGTH];
-    let mut aircrafts = HashMap::<Icao, Aircraft>::new();
-    let rec = rerun::RecordingStreamBuilder::new("stribog").connect_grpc()?;
-
-    let mut last_tick = Instant::now();
-
-    loop {
-        match sdr.read_sync(&mut buf) {
-            Ok(n) => {
-                let mut samples: Vec<f32> = Vec::with_capacity(n / 2);
-                for c in buf[..n].chunks_exact(2) {
-                    let i = (c[0] as f32 - 127.0) / 127.0;
-                    let q = (c[1] as f32 - 127.0) / 127.0;
-
-                    // let mag = ().sqrt
-                    let mag = (i * i + q * q).sqrt();
-                    // let mag = i.hypot(q);
-                    samples.push(mag);
-                    bitmap.write_all(&[(mag * 255.0) as u8; 3])?;
-                }
-
-                for samples in samples.windows(240) {
-                    if last_tick.elapsed().as_millis() > 100 {
-                        for (_, craft) in aircrafts.iter_mut() {
-                            if craft.latlong().is_none() {
-                                continue;
-                            }
-
-                            let Some(velocity_kts) = craft.velocity_kts else {
-                                continue;
-                            };
-
-                            let d_meters_per_sec = velocity_kts * 0.5144444444;
-                            let d_meters_per_tick = d_meters_per_sec / 10.0;
-                            let [dx, dy] = d_meters_per_tick.to_array();
-                            const R: f64 = 6_371_000.0;
-                            let dlat = dy / R;
-                            let dlon = dx / (R * craft.latitude_interpolated.to_radians().cos());
-
-                            craft.latitude_interpolated += dlat.to_degrees();
-                            craft.longitude_interpolated += dlon.to_degrees();
-
-                            craft.log_rerun(&rec)?;
-                        }
-                        // send interpolated samples for all aircrafts to rerun for visualization sake
-                        last_tick = Instant::now();
-                    }
-
-                    if check_preamble(samples) {
-                        let data_raw = &samples[16..];
-                        // let avg_amp = data_raw.iter().sum::<f32>() / data_raw.len() as f32;
-                        // if avg_amp < 0.1 {
-                        //     continue;
-                        // }
-                        let mut data_bytes = pulses_to_bytes(data_raw);
-
-                        let downlink_format = data_bytes[0] >> 3;
-
-                        let bits = if downlink_format <= 11 { 56 } else { 112 };
-                        let crc_calculated = mode_s_checksum(&data_bytes, bits);
-                        let crc_message = {
-                            let end = bits / 8;
-                            (data_bytes[end - 3] as u32) << 16
-                                | (data_bytes[end - 2] as u32) << 8
-                                | data_bytes[end - 1] as u32
-                        };
-
-                        if crc_calculated != crc_message {
-                            if let Some(fix_pos) = fix_single_bit_error(&mut data_bytes, bits) {
-                                warn!("Fixed bit at position {}", fix_pos);
-                            } else {
-                                // TODO(cohae): double bit error correction is quite slow. if we want to use it we will need to parallelize the program first
-                                // if let Some(fix_pos) = fix_double_bit_error(&mut data_bytes, bits) {
-                                //     warn!("Fixed 2 bits at position {}", fix_pos);
-                                // } else {
-                                //     // error!(
-                                //     //     "CRC mismatch: calculated {crc_calculated:08X}, message {crc_message:08X}"
-                                //     // );
-                                continue;
-                                // }
-                            }
-                        }
-                        info!("CRC ok ({crc_calculated:08X})");
-                        info!(
-                            "*{}",
-                            data_bytes
-                                .iter()
-                                .map(|&b| format!("{:02x}", b))
-                                .collect::<Vec<_>>()
-                                .join("")
-                        );
-
-                        let mode = match downlink_format {
-                            0 => Some("Short air-air surveillance (ACAS)"),
-                            4 => Some("Surveillance, altitude reply"),
-                            5 => Some("Surveillance, identity reply"),
-                            11 => Some("All-Call reply"),
-                            16 => Some("Long air-air surveillance (ACAS)"),
-                            17 => Some("Extended squitter"),
-                            18 => Some("Extended squitter/non transponder"),
-                            19 => Some("Military extended squitter"),
-                            20 => Some("Comm-B, altitude reply"),
-                            21 => Some("Comm-B, identity reply"),
-                            // "Format number 24 is identified using only the first two bits, which must be 11 in binary. All following bits are used for encoding other information"
-                            24..=31 => Some("Comm-D (ELM)"),
-                            _ => None,
-                        };
-                        info!("  Mode: {mode:?} ({downlink_format})");
-                        let craft = if matches!(downlink_format, 11 | 17 | 18) {
-                            let ca = data_bytes[0] & 0b111;
-                            let icao = (data_bytes[1] as u32) << 16
-                                | (data_bytes[2] as u32) << 8
-                                | data_bytes[3] as u32;
-                            info!("ICAO: {icao:06X}, DF: {downlink_format}, CA: {ca:02X}");
-                            aircrafts
-                                .entry(Icao::new(icao))
-                                .or_insert_with(|| Aircraft::new(Icao::new(icao)))
-                        } else {
-                            warn!("Unhandled Mode S DF {downlink_format}");
-                            continue;
-                        };
-
-                        if matches!(downlink_format, 17 | 18) {
-                            let mut msg = BitReader::new(Cow::Borrowed(&data_bytes[4..11]));
-                            let message_type = msg.read_bits(5);
-                            let message_typename = match message_type {
-                                1..=4 => "Aircraft identification",
-                                5..=8 => "Surface position",
-                                9..=18 => "Airborne position (w/Baro Altitude)",
-                                19 => "Airborne velocities",
-                                20..=22 => "Airborne position (w/GNSS Height)",
-                                23..=27 => "Reserved",
-                                28 => "Aircraft status",
-                                29 => "Target state and status information",
-                                31 => "Aircraft operation status",
-                                _ => {
-                                    error!("Unknown message type: {}", message_type);
-                                    continue;
-                                }
-                            };
-
-                            info!("ADS-B message '{message_typename}' ({message_type})");
-                            info!(
-                                "ADS-B message data: {}",
-                                msg.data()
-                                    .iter()
-                                    .map(|b| format!("{:02X}", b))
-                                    .collect::<Vec<_>>()
-                                    .join("")
-                            );
-
-                            match message_type {
-                                1..=4 => {
-                                    let aircraft_type = msg.read_bits(3);
-                                    let aircraft_category = match (message_type, aircraft_type) {
-                                        (1, _) => "Reserved",
-                                        (_, 0) => "No category information",
-                                        (2, 1) => "Surface emergency vehicle",
-                                        (2, 3) => "Surface service vehicle",
-                                        (2, 4..=7) => "Ground obstruction",
-                                        (3, 1) => "Glider, sailplane",
-                                        (3, 2) => "Lighter-than-air",
-                                        (3, 3) => "Parachutist, skydiver",
-                                        (3, 4) => "Ultralight, hang-glider, paraglider",
-                                        (3, 5) => "Reserved",
-                                        (3, 6) => "Unmanned aerial vehicle",
-                                        (3, 7) => "Space or transatmospheric vehicle",
-                                        (4, 1) => "Light (less than 7000 kg)",
-                                        (4, 2) => "Medium 1 (between 7000 kg and 34000 kg)",
-                                        (4, 3) => "Medium 2 (between 34000 kg to 136000 kg)",
-                                        (4, 4) => "High vortex aircraft",
-                                        (4, 5) => "Heavy (larger than 136000 kg)",
-                                        (4, 6) => {
-                                            "High performance (>5 g acceleration) and high speed (>400 kt)"
-                                        }
-                                        (4, 7) => "Rotorcraft",
-                                        _ => "<unknown wake vortex category>",
-                                    };
-
-                                    info!("  Aircraft category: {}", aircraft_category);
-
-                                    let mut callsign = String::new();
-                                    const AIS_LOOKUP: &str = "#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######";
-                                    for _ in 0..8 {
-                                        callsign.push(
-                                            AIS_LOOKUP
-                                                .chars()
-                                                .nth(msg.read_bits(6) as usize)
-                                                .unwrap(),
-                                        );
-                                    }
-
-                                    callsign = callsign.trim_end_matches("#").trim().to_string();
-                                    info!("  Callsign: {}", callsign);
-                                    craft.callsign = Some(callsign.clone());
-
-                                    // rec.log(
-                                    //     format!("world/plane/{}", craft.icao),
-                                    //     &rerun::TextDocument::from_markdown(format!(
-                                    //         "**Callsign:** {callsign}"
-                                    //     )),
-                                    // )?;
-                                    rec.log(
-                                        "logs",
-                                        &rerun::TextLog::new(format!(
-                                            "Craft {} updated callsign to {callsign}",
-                                            craft.icao
-                                        ))
-                                        .with_level(rerun::TextLogLevel::DEBUG),
-                                    )?;
-                                }
-                                9..=18 | 20..=22 => {
-                                    let surveillance_status = match msg.read_bits(2) {
-                                        0 => "No condition",
-                                        1 => "Permanent alert",
-                                        2 => "Temporary alert",
-                                        3 => "Special condition",
-                                        _ => unreachable!(),
-                                    };
-
-                                    let _single_antenna = msg.read_bit();
-                                    let encoded_altitude = msg.read_bits(12);
-                                    let _time = msg.read_bits(1);
-                                    let is_odd_frame = msg.read_bit();
-                                    let encoded_latitude = msg.read_bits(17);
-                                    let encoded_longitude = msg.read_bits(17);
-
-                                    if is_odd_frame {
-                                        craft.odd_cprlat = encoded_latitude;
-                                        craft.odd_cprlon = encoded_longitude;
-                                        craft.odd_cprtime = Instant::now();
-                                    } else {
-                                        craft.even_cprlat = encoded_latitude;
-                                        craft.even_cprlon = encoded_longitude;
-                                        craft.even_cprtime = Instant::now();
-                                    }
-                                    craft.update_latlong();
-
-                                    let altitude = if (9..=18).contains(&message_type) {
-                                        let q = (encoded_altitude & (1 << 8)) != 0;
-                                        let alt_low = encoded_altitude & 0b1111;
-                                        let alt_high = encoded_altitude >> 5;
-                                        let alt_fixed = alt_high << 4 | alt_low;
-                                        if q {
-                                            Altitude::Feet(alt_fixed * 25 - 1000)
-                                        } else {
-                                            Altitude::Feet(gray_to_binary(alt_fixed) * 100)
-                                        }
-                                    } else {
-                                        Altitude::Meters(encoded_altitude)
-                                    };
-                                    craft.altitude = Some(altitude);
-
-                                    info!("  Surveillance status: {}", surveillance_status);
-                                    info!(
-                                        "  Altitude: {:?}, {:?}",
-                                        altitude.to_feet(),
-                                        altitude.to_meters()
-                                    );
-                                    info!("  Latitude: {} (not decoded)", encoded_latitude);
-                                    info!("  Longitude: {} (not decoded)", encoded_longitude);
-                                    info!("  Lat/Long: {:?}", craft.latlong());
-
-                                    if craft.latlong().is_some() {
-                                        craft.log_rerun(&rec)?;
-                                    }
-                                }
-                                19 => {
-                                    let subtype = msg.read_bits(3);
-                                    let _intent_change = msg.read_bit();
-                                    let _ifr_capable = msg.read_bit();
-                                    let _nuc = msg.read_bits(3);
-                                    match subtype {
-                                        // Ground-based velocity (despite it's name also applies to airborne aircraft)
-                                        1 | 2 => {
-                                            let horizontal_dir = msg.read_bits(1); // 0=east, 1=west
-                                            let mut horizontal_speed = msg.read_bits(10) - 1;
-                                            let vertical_dir = msg.read_bits(1); // 0=north, 1=south
-                                            let mut vertical_speed = msg.read_bits(10) - 1;
-
-                                            let hsign = if horizontal_dir == 1 { -1 } else { 1 };
-                                            let vsign = if vertical_dir == 1 { -1 } else { 1 };
-
-                                            // Used for supersonic aircraft (rarely, if ever seen these days)
-                                            if subtype == 2 {
-                                                horizontal_speed *= 4;
-                                                vertical_speed *= 4;
-                                            }
-
-                                            let velocity = DVec2::new(
-                                                horizontal_speed as f64 * hsign as f64,
-                                                vertical_speed as f64 * vsign as f64,
-                                            );
-
-                                            craft.velocity_kts = Some(velocity);
-                                        }
-                                        u => error!("Unhandled velocity subtype {u}"),
-                                    }
-                                }
-                                u => {
-                                    error!("Unhandled ADS-B message type: {u}");
-                                }
-                            }
-                        }
-
-                        println!();
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Read error: {e:#?}");
-            }
-        }
+    let clock = SampleClock::new(args.sample_rate, start);
+    let mut pipeline = Pipeline::new(&args, clock)?;
+
+    if let Some((log, path)) = replay.zip(args.replay.as_ref()) {
+        let source = pipeline
+            .stats
+            .add_source(format!("replay:{}", path.display()));
+        return run_replay(&mut pipeline, log, source, args.replay_speed);
     }
-}
-
-/// Convert a Gray code to binary.
-fn gray_to_binary(mut gray: u32) -> u32 {
-    let mut binary = 0;
-    let mut mask = 1;
 
-    for _ in 0..32 {
-        let bit = gray & mask;
-        binary |= bit;
-        gray ^= bit;
-        mask <<= 1;
+    let (tx, inputs) = mpsc::channel();
+    for spec in &args.net_connect {
+        let source = pipeline.stats.add_source(spec.to_string());
+        input::connect(spec.clone(), source, tx.clone())?;
     }
-
-    binary
-}
-
-fn check_preamble(m: &[f32]) -> bool {
-    if m.len() < 16 {
-        return false;
+    for spec in &args.net_listen {
+        let source = pipeline.stats.add_source(spec.to_string());
+        input::listen(spec.clone(), source, tx.clone())?;
     }
-
-    // Preamble 1010000101000000
-    if !(m[0] > m[1]
-        && m[1] < m[2]
-        && m[2] > m[3]
-        && m[3] < m[0]
-        && m[4] < m[0]
-        && m[5] < m[0]
-        && m[6] < m[0]
-        && m[7] > m[8]
-        && m[8] < m[9]
-        && m[9] > m[6])
-    {
-        return false;
+    for spec in &args.input_file {
+        let source = pipeline.stats.add_source(spec.to_string());
+        input::read_file(spec.clone(), source, tx.clone())?;
     }
+    drop(tx);
 
-    // let hi0 = magnitude[0]; // 1
-    // let lo1 = magnitude[1]; // 0
-    // let hi2 = magnitude[2]; // 1
-    // let lo3 = magnitude[3]; // 0
-    // let lo4 = magnitude[4]; // 0
-    // let lo5 = magnitude[5]; // 0
-    // let lo6 = magnitude[6]; // 0
-    // let hi7 = magnitude[7]; // 1
-    // let lo8 = magnitude[8]; // 0
-    // let hi9 = magnitude[9]; // 1
-    // let lo10 = magnitude[10]; // 0
-    // let lo11 = magnitude[11]; // 0
-    // let lo12 = magnitude[12]; // 0
-    // let lo13 = magnitude[13]; // 0
-    // let lo14 = magnitude[14]; // 0
-    // let lo15 = magnitude[15]; // 0
-
-    // let avg_hi = (hi0 + hi2 + hi7 + hi9) / 4.0;
-    // let avg_lo =
-    //     (lo1 + lo3 + lo4 + lo5 + lo6 + lo8 + lo10 + lo11 + lo12 + lo13 + lo14 + lo15) / 12.0;
-
-    // if avg_hi < avg_lo {
-    //     return false;
-    // }
-
-    // // Ensure all low samples are below the average high sample
-    // if lo1 > avg_hi
-    //     || lo3 > avg_hi
-    //     || lo4 > avg_hi
-    //     || lo5 > avg_hi
-    //     || lo6 > avg_hi
-    //     || lo8 > avg_hi
-    //     || lo10 > avg_hi
-    //     || lo11 > avg_hi
-    //     || lo12 > avg_hi
-    //     || lo13 > avg_hi
-    //     || lo14 > avg_hi
-    //     || lo15 > avg_hi
-    // {
-    //     return false;
-    // }
-
-    // // Ensure all high samples are above the average low sample
-    // if hi0 < avg_lo || hi2 < avg_lo || hi7 < avg_lo || hi9 < avg_lo {
-    //     return false;
-    // }
-
-    // // Check peaks/valleys
-    // if hi0 < lo1 || lo1 > hi2 || hi2 < lo3 || lo6 > hi7 || hi7 < lo8 || lo8 > hi9 || hi9 < lo10 {
-    //     return false;
-    // }
-
-    true
-}
-
-fn pulses_to_bytes(magnitudes: &[f32]) -> Vec<u8> {
-    let mut bits = vec![false; magnitudes.len() / 2];
-
-    for (i, pulse_set) in magnitudes.chunks_exact(2).enumerate() {
-        let low = pulse_set[0];
-        let high = pulse_set[1];
-
-        bits[i] = low > high;
+    if args.no_sdr {
+        anyhow::ensure!(
+            !pipeline.stats.sources.is_empty(),
+            "--no-sdr needs at least one --net-connect, --net-listen or --input-file input"
+        );
+        run_inputs(&mut pipeline, &inputs)
+    } else {
+        let samples = match &args.iq_file {
+            Some(path) => sdr::Samples::File(path.clone(), args.iq_format),
+            None => sdr::Samples::Sdr,
+        };
+        let source = pipeline.stats.add_source(samples.to_string());
+        let worker = sdr::Worker {
+            demodulator: Demodulator::new(args.sample_rate, args.preamble_snr),
+            mode_ac: args
+                .mode_ac
+                .then(|| ModeAcDemodulator::new(args.sample_rate, args.mode_ac_snr)),
+            known: pipeline.tracker.known_addresses(),
+            source,
+        };
+        let blocks = sdr::start(worker, args.demod_threads, samples)?;
+        run_sdr(&mut pipeline, blocks, &inputs)
     }
+}
 
-    let mut bytes = Vec::with_capacity(bits.len() / 8);
+/// Feed a frame log through the pipeline, `speed` times faster than it was recorded or as fast as possible if 0
+fn run_replay(
+    pipeline: &mut Pipeline,
+    log: FrameLog,
+    source: SourceId,
+    speed: f64,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let mut first = None;
+    for frame in log {
+        let mut frame = frame?;
+        frame.source = source;
+
+        let first = *first.get_or_insert(frame.timestamp);
+        if speed > 0.0 {
+            let due = frame
+                .timestamp
+                .saturating_duration_since(first)
+                .div_f64(speed);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
 
-    for c in bits.chunks_exact(8) {
-        bytes.push(
-            (c[0] as u8) << 7
-                | (c[1] as u8) << 6
-                | (c[2] as u8) << 5
-                | (c[3] as u8) << 4
-                | (c[4] as u8) << 3
-                | (c[5] as u8) << 2
-                | (c[6] as u8) << 1
-                | (c[7] as u8),
-        );
+        pipeline.tick(frame.timestamp)?;
+        pipeline.handle_frame(frame)?;
     }
 
-    bytes
+    info!(
+        "Replay finished: {} messages, {} aircraft tracked",
+        pipeline.stats.messages, pipeline.stats.tracks_new
+    );
+    Ok(())
 }
 
-const MODE_S_CHECKSUM_LUT: [u32; 112] = [
-    0x3935ea, 0x1c9af5, 0xf1b77e, 0x78dbbf, 0xc397db, 0x9e31e9, 0xb0e2f0, 0x587178, 0x2c38bc,
-    0x161c5e, 0x0b0e2f, 0xfa7d13, 0x82c48d, 0xbe9842, 0x5f4c21, 0xd05c14, 0x682e0a, 0x341705,
-    0xe5f186, 0x72f8c3, 0xc68665, 0x9cb936, 0x4e5c9b, 0xd8d449, 0x939020, 0x49c810, 0x24e408,
-    0x127204, 0x093902, 0x049c81, 0xfdb444, 0x7eda22, 0x3f6d11, 0xe04c8c, 0x702646, 0x381323,
-    0xe3f395, 0x8e03ce, 0x4701e7, 0xdc7af7, 0x91c77f, 0xb719bb, 0xa476d9, 0xadc168, 0x56e0b4,
-    0x2b705a, 0x15b82d, 0xf52612, 0x7a9309, 0xc2b380, 0x6159c0, 0x30ace0, 0x185670, 0x0c2b38,
-    0x06159c, 0x030ace, 0x018567, 0xff38b7, 0x80665f, 0xbfc92b, 0xa01e91, 0xaff54c, 0x57faa6,
-    0x2bfd53, 0xea04ad, 0x8af852, 0x457c29, 0xdd4410, 0x6ea208, 0x375104, 0x1ba882, 0x0dd441,
-    0xf91024, 0x7c8812, 0x3e4409, 0xe0d800, 0x706c00, 0x383600, 0x1c1b00, 0x0e0d80, 0x0706c0,
-    0x038360, 0x01c1b0, 0x00e0d8, 0x00706c, 0x003836, 0x001c1b, 0xfff409, 0x000000, 0x000000,
-    0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000,
-    0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000, 0x000000,
-    0x000000, 0x000000, 0x000000, 0x000000,
-];
-
-fn mode_s_checksum(data: &[u8], bits: usize) -> u32 {
-    let mut crc = 0u32;
-    let offset = if bits == 112 { 0 } else { 112 - 56 };
-
-    for j in 0..bits {
-        let byte = j / 8;
-        let bit = j % 8;
-        let mask = 1 << (7 - bit);
-        if data[byte] & mask != 0 {
-            crc ^= MODE_S_CHECKSUM_LUT[j + offset];
+/// Decode network and file inputs only
+fn run_inputs(pipeline: &mut Pipeline, inputs: &Receiver<Input>) -> anyhow::Result<()> {
+    loop {
+        match inputs.recv_timeout(Duration::from_millis(100)) {
+            Ok(input) => pipeline.handle_input(input)?,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
         }
-    }
 
-    crc
+        pipeline.tick(pipeline.timestamp_of(SystemTime::now()))?;
+    }
 }
 
-fn fix_single_bit_error(data: &mut [u8], bits: usize) -> Option<usize> {
-    let mut corrected = [0u8; 112 / 8];
-
-    for j in 0..bits {
-        let byte = j / 8;
-        let bit = j % 8;
-        let bitmask = 1 << (7 - bit);
+/// Decode the frames demodulated from the SDR, and network and file inputs in between blocks
+fn run_sdr(
+    pipeline: &mut Pipeline,
+    mut blocks: sdr::Blocks,
+    inputs: &Receiver<Input>,
+) -> anyhow::Result<()> {
+    loop {
+        match blocks.recv_timeout(Duration::from_millis(100)) {
+            Ok(block) => {
+                let stats = &mut pipeline.stats;
+                stats.samples += block.samples;
+                stats.preambles += block.preambles;
+                stats.buffers_dropped += block.dropped_buffers;
+                stats.samples_dropped += block.dropped_samples;
+                stats.add_noise_floor(block.noise_floor);
+                stats.dc_offset = Some(block.dc_offset);
+
+                for frame in block.frames {
+                    pipeline.tick(frame.timestamp)?;
+                    pipeline.handle_frame(frame)?;
+                }
+                for reply in &block.mode_ac {
+                    pipeline.handle_mode_ac(reply);
+                }
+                pipeline
+                    .clock
+                    .advance((block.dropped_samples + block.samples) as usize);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // Only an IQ file runs out of samples
+            Err(RecvTimeoutError::Disconnected) => {
+                info!(
+                    "End of samples: {} messages, {} aircraft tracked",
+                    pipeline.stats.messages, pipeline.stats.tracks_new
+                );
+                return Ok(());
+            }
+        }
 
-        corrected.copy_from_slice(data);
-        corrected[byte] ^= bitmask;
-        let end = bits / 8;
-        let crc1 = (corrected[end - 3] as u32) << 16
-            | (corrected[end - 2] as u32) << 8
-            | corrected[end - 1] as u32;
-        let crc2 = mode_s_checksum(&corrected, bits);
-        if crc1 == crc2 {
-            data[0..112 / 8].copy_from_slice(&corrected);
-            return Some(j);
+        while let Ok(input) = inputs.try_recv() {
+            pipeline.handle_input(input)?;
         }
+        pipeline.tick(pipeline.clock.timestamp_at(0))?;
     }
-
-    None
 }
-
-// fn fix_double_bit_error(data: &mut [u8], bits: usize) -> Option<usize> {
-//     let mut corrected = [0u8; 112 / 8];
-
-//     for j in 0..bits {
-//         let byte1 = j / 8;
-//         let bit1 = j % 8;
-//         let bitmask1 = 1 << (7 - bit1);
-
-//         for i in j + 1..bits {
-//             let byte2 = i / 8;
-//             let bit2 = i % 8;
-//             let bitmask2 = 1 << (7 - bit2);
-
-//             corrected.copy_from_slice(data);
-//             corrected[byte1] ^= bitmask1;
-//             corrected[byte2] ^= bitmask2;
-
-//             let end = bits / 8;
-//             let crc1 = (corrected[end - 3] as u32) << 16
-//                 | (corrected[end - 2] as u32) << 8
-//                 | corrected[end - 1] as u32;
-//             let crc2 = mode_s_checksum(&corrected, bits);
-//             if crc1 == crc2 {
-//                 data[0..112 / 8].copy_from_slice(&corrected);
-//                 return Some(j);
-//             }
-//         }
-//     }
-
-//     None
-// }
@@ -0,0 +1,234 @@
+//! Demodulation of Mode A/C replies: up to 15 pulses 1.45 µs apart, framed by the F1 and F2 pulses, followed by an
+//! optional SPI pulse.
+//!
+//! The 13 positions between F1 and F2 are in the same order as the identity field of Mode S replies
+//! (C1 A1 C2 A2 C4 A4 X B1 D1 B2 D2 B4 D4). Whether a reply is a Mode A identity or a Mode C altitude depends on the
+//! interrogation, which we don't hear, so every reply can be read both ways.
+
+use std::time::Duration;
+
+use crate::{
+    altitude::{gillham_to_hundreds, id13_to_mode_a},
+    demod::{PHASES, SAMPLE_WEIGHT, Slot},
+    magnitude,
+    timestamp::Timestamp,
+};
+
+const PULSE_SPACING_US: f32 = 1.45;
+const PULSE_US: f32 = 0.45;
+
+/// F1 is the first position and F2 the last
+const F2: usize = 14;
+/// The unused X position, which must stay quiet
+const X: usize = 7;
+/// The SPI (ident) pulse comes three positions after F2
+const SPI: usize = 17;
+
+/// F1 and F2 may differ by up to a factor 2 in magnitude (6 dB), or they belong to different replies
+const MAX_FRAMING_RATIO: u32 = 2;
+
+/// Bit of the Beast data that carries the SPI pulse, unused by the Mode A code
+const BEAST_SPI: u16 = 0x0080;
+
+/// A demodulated Mode A/C reply
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModeAcReply {
+    /// The 13 positions between F1 and F2, C1 being the most significant bit
+    pub id13: u32,
+    /// Special position identification, the ident button
+    pub spi: bool,
+    pub timestamp: Timestamp,
+    /// Mean power of the pulses relative to full scale (0.0-1.0)
+    pub signal_level: f32,
+}
+
+impl ModeAcReply {
+    /// The reply read as a Mode A code, one octal digit per nibble (eg. 0x7700)
+    pub fn mode_a(&self) -> u16 {
+        id13_to_mode_a(self.id13)
+    }
+
+    /// The reply read as a Mode C altitude in feet, if it is a valid Gillham code
+    pub fn altitude_ft(&self) -> Option<i32> {
+        gillham_to_hundreds(self.mode_a()).map(|hundreds| hundreds * 100)
+    }
+
+    /// The two data bytes of a Beast Mode A/C message: the Mode A code, with the SPI pulse in bit 0x0080
+    pub fn beast_data(&self) -> [u8; 2] {
+        let spi = if self.spi { BEAST_SPI } else { 0 };
+        (self.mode_a() | spi).to_be_bytes()
+    }
+}
+
+/// The slots of every pulse position, and of the gaps between F1 and F2, at one phase
+#[derive(Debug, Clone)]
+struct ReplySlots {
+    phase: f32,
+    pulses: [Slot; SPI + 1],
+    /// Between two positions, where there is never a pulse
+    gaps: [Slot; F2],
+}
+
+/// A slot of up to `len` samples between the pulse ending at `from` and the one starting at `to`.
+///
+/// Only whole samples that don't overlap either pulse are used, so a strong pulse can't leak into the gap. There is
+/// always at least one such sample, the gap is 1 µs long
+fn gap_slot(from: f32, to: f32, len: f32, total: u32) -> Slot {
+    let (first, last) = (from.ceil(), to.floor());
+    let width = (last - first).min(len.ceil()).max(1.0);
+    Slot::new(first + ((last - first - width) / 2.0).floor(), width, total)
+}
+
+#[derive(Clone)]
+pub struct ModeAcDemodulator {
+    sample_rate: u32,
+    samples_per_us: f32,
+    /// How much stronger than the loudest gap the framing pulses must be
+    min_ratio: f32,
+    phases: Vec<ReplySlots>,
+    /// Level of a slot where every sample is at full scale
+    full_scale: f32,
+}
+
+impl ModeAcDemodulator {
+    pub fn new(sample_rate: u32, min_snr_db: f32) -> Self {
+        let samples_per_us = sample_rate as f32 / 1e6;
+        let spacing = PULSE_SPACING_US * samples_per_us;
+        let len = PULSE_US * samples_per_us;
+        let total = (len * SAMPLE_WEIGHT).round() as u32;
+        let phases = PHASES
+            .iter()
+            .map(|&phase| ReplySlots {
+                phase,
+                pulses: std::array::from_fn(|i| Slot::new(phase + i as f32 * spacing, len, total)),
+                gaps: std::array::from_fn(|i| {
+                    let position = phase + i as f32 * spacing;
+                    gap_slot(position + len, position + spacing, len, total)
+                }),
+            })
+            .collect();
+
+        ModeAcDemodulator {
+            sample_rate,
+            samples_per_us,
+            min_ratio: 10f32.powf(min_snr_db / 20.0),
+            phases,
+            full_scale: total as f32 * magnitude::FULL_SCALE,
+        }
+    }
+
+    /// Number of samples from F1 to the end of F2
+    pub fn reply_length(&self) -> usize {
+        ((F2 as f32 * PULSE_SPACING_US + PULSE_US) * self.samples_per_us) as usize
+    }
+
+    /// Number of samples needed to demodulate a reply at every phase, including the SPI pulse
+    pub fn window(&self) -> usize {
+        ((SPI as f32 * PULSE_SPACING_US + PULSE_US) * self.samples_per_us).ceil() as usize + 2
+    }
+
+    /// Demodulate a reply whose F1 pulse starts at the beginning of `m`, the sample at `start`.
+    ///
+    /// Both framing pulses must be present, of similar strength and at least `min_snr_db` above every gap between
+    /// positions. The phase with the clearest framing is kept.
+    pub fn demodulate(&self, m: &[u16], start: Timestamp) -> Option<ModeAcReply> {
+        if m.len() < self.window() {
+            return None;
+        }
+
+        let mut best: Option<(u32, ModeAcReply)> = None;
+        for slots in &self.phases {
+            let f1 = slots.pulses[0].level(m);
+            let f2 = slots.pulses[F2].level(m);
+            let weakest = f1.min(f2);
+            if weakest == 0 || f1.max(f2) > weakest * MAX_FRAMING_RATIO {
+                continue;
+            }
+
+            // Without a CRC, the framing pulses must stand out from every gap, not just on average
+            let gaps = slots.gaps.map(|slot| slot.level(m));
+            let loudest_gap = gaps.iter().copied().max()?;
+            if (weakest as f32) < loudest_gap as f32 * self.min_ratio {
+                continue;
+            }
+
+            // Code pulses are as strong as the framing pulses, empty positions as quiet as the gaps. Anything in
+            // between is most likely another reply overlapping this one
+            let threshold = weakest / 2;
+            let levels = slots.pulses.map(|slot| slot.level(m));
+            let ambiguous = |level: u32| level > loudest_gap * 3 / 2 && level <= threshold;
+            if levels[X] > threshold || levels[1..F2].iter().any(|&level| ambiguous(level)) {
+                continue;
+            }
+
+            let id13 = levels[1..F2]
+                .iter()
+                .fold(0, |id13, &level| id13 << 1 | (level > threshold) as u32);
+            let present = levels[..=F2]
+                .iter()
+                .filter(|&&level| level > threshold)
+                .map(|&level| (level as f32 / self.full_scale).powi(2))
+                .collect::<Vec<_>>();
+
+            let clearance = weakest - loudest_gap;
+            if best.as_ref().is_none_or(|(best, _)| clearance > *best) {
+                let delay = Duration::from_secs_f64(slots.phase as f64 / self.sample_rate as f64);
+                best = Some((
+                    clearance,
+                    ModeAcReply {
+                        id13,
+                        spi: levels[SPI] > threshold,
+                        timestamp: start + delay,
+                        signal_level: (present.iter().sum::<f32>() / present.len() as f32).min(1.0),
+                    },
+                ));
+            }
+        }
+
+        best.map(|(_, reply)| reply)
+    }
+}
+
+#[test]
+fn test_mode_ac_reply() {
+    use crate::demod::pulse_magnitudes;
+
+    // Squawk 1200 (A1 and B2), ident
+    let positions = [0, 2, 10, F2, SPI];
+    for sample_rate in [2_000_000, 2_400_000] {
+        let demodulator = ModeAcDemodulator::new(sample_rate, 6.0);
+
+        // At half of full scale, after a quarter microsecond of noise
+        let pulses = positions.map(|i| {
+            let start = i as f32 * PULSE_SPACING_US;
+            (start, start + PULSE_US)
+        });
+        let m = pulse_magnitudes(&pulses, sample_rate, 0.25, demodulator.window() + 2)
+            .into_iter()
+            .map(|m| m + 100)
+            .collect::<Vec<_>>();
+
+        let reply = (0..3)
+            .find_map(|offset| demodulator.demodulate(&m[offset..], Timestamp::default()))
+            .unwrap();
+        assert_eq!(reply.mode_a(), 0x1200, "at {sample_rate} S/s");
+        assert!(reply.spi);
+        assert_eq!(reply.beast_data(), [0x12, 0x80]);
+    }
+
+    // C4 alone is the lowest Gillham altitude
+    let reply = ModeAcReply {
+        id13: 0x0100,
+        spi: false,
+        timestamp: Timestamp::default(),
+        signal_level: 0.25,
+    };
+    assert_eq!(reply.altitude_ft(), Some(-1200));
+
+    // Noise
+    let demodulator = ModeAcDemodulator::new(2_000_000, 6.0);
+    assert_eq!(
+        demodulator.demodulate(&vec![1000; demodulator.window()], Timestamp::default()),
+        None
+    );
+}
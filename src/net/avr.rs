@@ -0,0 +1,80 @@
+use std::fmt::Write;
+
+use crate::frame::Frame;
+
+/// Encode a frame in AVR text format, either `*8D4840D6202CC371C32CE0576098;` or with an MLAT timestamp prefix as
+/// `@0000A1B2C3D48D4840D6202CC371C32CE0576098;`
+pub fn encode_frame(frame: &Frame, mlat: bool) -> String {
+    let mut line = String::with_capacity(1 + 12 + 28 + 2);
+    if mlat {
+        write!(line, "@{:012X}", frame.timestamp.ticks() & 0xFFFF_FFFF_FFFF).unwrap();
+    } else {
+        line.push('*');
+    }
+
+    for b in frame.data() {
+        write!(line, "{b:02X}").unwrap();
+    }
+
+    line.push_str(";\n");
+    line
+}
+
+/// Parse a line of AVR text, returning the MLAT timestamp if present and the frame bytes
+pub fn decode_line(line: &str) -> Option<(Option<u64>, Vec<u8>)> {
+    let line = line.trim().strip_suffix(';')?;
+    let (timestamp, hex) = if let Some(hex) = line.strip_prefix('*') {
+        (None, hex)
+    } else {
+        let line = line.strip_prefix('@')?;
+        let (timestamp, hex) = line.split_at_checked(12)?;
+        (Some(u64::from_str_radix(timestamp, 16).ok()?), hex)
+    };
+
+    Some((timestamp, decode_hex(hex)?))
+}
+
+/// Parse the hex digits of a short or long Mode S frame
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !matches!(hex.len(), 14 | 28) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[test]
+fn test_avr_encode() {
+    use crate::timestamp::Timestamp;
+
+    let data = [
+        0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+    ];
+    let frame = Frame::new(&data, Timestamp::from_samples(0x1234, 12_000_000), 0.5);
+    assert_eq!(
+        encode_frame(&frame, false),
+        "*8D4840D6202CC371C32CE0576098;\n"
+    );
+    assert_eq!(
+        encode_frame(&frame, true),
+        "@0000000012348D4840D6202CC371C32CE0576098;\n"
+    );
+}
+
+#[test]
+fn test_avr_decode() {
+    let (timestamp, data) = decode_line("@0000000012348D4840D6202CC371C32CE0576098;\r\n").unwrap();
+    assert_eq!(timestamp, Some(0x1234));
+    assert_eq!(data[..4], [0x8D, 0x48, 0x40, 0xD6]);
+    assert_eq!(data.len(), 14);
+
+    assert_eq!(decode_line("*5D4840D6;"), None);
+    assert_eq!(
+        decode_line("*8D4840D6202CC3;").map(|(_, d)| d.len()),
+        Some(7)
+    );
+    assert_eq!(decode_line("*8D4840D6202CCX;"), None);
+}
@@ -0,0 +1,161 @@
+//! Mode-S Beast binary protocol, as spoken by dump1090 and friends on port 30005.
+//!
+//! Every message starts with an escape byte (0x1A) and a type byte, followed by a 6-byte MLAT timestamp, a signal
+//! level byte and the frame data. Any 0x1A inside the message is doubled.
+
+use crate::{frame::Frame, timestamp::Timestamp};
+
+pub const ESCAPE: u8 = 0x1A;
+
+pub const TYPE_MODE_AC: u8 = b'1';
+pub const TYPE_MODE_S_SHORT: u8 = b'2';
+pub const TYPE_MODE_S_LONG: u8 = b'3';
+
+/// Encode a Mode S frame as a Beast message
+pub fn encode_frame(frame: &Frame) -> Vec<u8> {
+    encode(frame.timestamp, frame.signal_level, frame.data())
+}
+
+/// Encode a Mode A/C (2 bytes), short Mode S (7 bytes) or long Mode S (14 bytes) reply.
+///
+/// `signal_level` is the mean signal power relative to full scale (0.0-1.0)
+pub fn encode(timestamp: Timestamp, signal_level: f32, data: &[u8]) -> Vec<u8> {
+    let message_type = match data.len() {
+        2 => TYPE_MODE_AC,
+        7 => TYPE_MODE_S_SHORT,
+        14 => TYPE_MODE_S_LONG,
+        len => panic!("Invalid Beast frame length {len}"),
+    };
+
+    let mut out = Vec::with_capacity(2 + (6 + 1 + data.len()) * 2);
+    out.push(ESCAPE);
+    out.push(message_type);
+
+    let timestamp = timestamp.ticks().to_be_bytes();
+    let signal = (signal_level.clamp(0.0, 1.0).sqrt() * 255.0).round() as u8;
+    for &b in timestamp[2..].iter().chain([signal].iter()).chain(data) {
+        out.push(b);
+        if b == ESCAPE {
+            out.push(ESCAPE);
+        }
+    }
+
+    out
+}
+
+/// Inverse of the signal byte computed by [`encode`]
+pub fn decode_signal(signal: u8) -> f32 {
+    (signal as f32 / 255.0).powi(2)
+}
+
+/// A message read from a Beast stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeastMessage {
+    pub message_type: u8,
+    /// 12 MHz MLAT timestamp of the sender
+    pub timestamp: u64,
+    pub signal: u8,
+    pub data: Vec<u8>,
+}
+
+/// Incremental parser for a Beast byte stream, which may start or be cut off in the middle of a message
+#[derive(Default)]
+pub struct BeastReader {
+    buf: Vec<u8>,
+}
+
+impl BeastReader {
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Take the next complete message out of the buffer. Unknown message types are skipped
+    pub fn next_message(&mut self) -> Option<BeastMessage> {
+        'sync: loop {
+            let start = self.buf.iter().position(|&b| b == ESCAPE);
+            self.buf.drain(..start.unwrap_or(self.buf.len()));
+            if self.buf.len() < 2 {
+                return None;
+            }
+
+            let data_len = match self.buf[1] {
+                TYPE_MODE_AC => 2,
+                TYPE_MODE_S_SHORT => 7,
+                TYPE_MODE_S_LONG => 14,
+                _ => {
+                    // Not a message start (eg. an escaped 0x1A in a message we joined halfway)
+                    self.buf.drain(..1);
+                    continue;
+                }
+            };
+
+            // Timestamp, signal level and data
+            let len = 6 + 1 + data_len;
+            let mut payload = Vec::with_capacity(len);
+            let mut i = 2;
+            while payload.len() < len {
+                let b = *self.buf.get(i)?;
+                if b == ESCAPE {
+                    if *self.buf.get(i + 1)? != ESCAPE {
+                        // A lone escape byte starts the next message, this one was truncated
+                        self.buf.drain(..i);
+                        continue 'sync;
+                    }
+                    i += 1;
+                }
+
+                payload.push(b);
+                i += 1;
+            }
+
+            let message_type = self.buf[1];
+            self.buf.drain(..i);
+            return Some(BeastMessage {
+                message_type,
+                timestamp: payload[..6].iter().fold(0, |acc, &b| acc << 8 | b as u64),
+                signal: payload[6],
+                data: payload[7..].to_vec(),
+            });
+        }
+    }
+}
+
+#[test]
+fn test_beast_escape() {
+    let data = [0x8D, 0x1A, 0x40, 0xD6, 0x20, 0x2C, 0xC3];
+    let encoded = encode(Timestamp::from_samples(0x1A, 12_000_000), 1.0, &data);
+    assert_eq!(
+        encoded,
+        [
+            &[ESCAPE, TYPE_MODE_S_SHORT][..],
+            &[0x00, 0x00, 0x00, 0x00, 0x00, 0x1A, 0x1A],
+            &[0xFF],
+            &[0x8D, 0x1A, 0x1A, 0x40, 0xD6, 0x20, 0x2C, 0xC3],
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn test_beast_reader() {
+    let data = [0x8D, 0x1A, 0x40, 0xD6, 0x20, 0x2C, 0xC3];
+    let encoded = encode(Timestamp::from_samples(0x1A, 12_000_000), 1.0, &data);
+
+    let mut reader = BeastReader::default();
+    // Garbage and a truncated message before the real one
+    reader.push(&[0x00, 0x1A, 0x1A, ESCAPE, TYPE_MODE_S_LONG, 0x01]);
+    reader.push(&encoded[..5]);
+    assert_eq!(reader.next_message(), None);
+
+    reader.push(&encoded[5..]);
+    assert_eq!(
+        reader.next_message(),
+        Some(BeastMessage {
+            message_type: TYPE_MODE_S_SHORT,
+            timestamp: 0x1A,
+            signal: 0xFF,
+            data: data.to_vec(),
+        })
+    );
+    assert_eq!(reader.next_message(), None);
+}
@@ -0,0 +1,285 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    aircraft::{Aircraft, Icao},
+    export::{self, TrackFormat},
+    stats::{SourceStats, Stats},
+    timestamp::Timestamp,
+    tracker::Tracker,
+};
+
+/// The state served by the API, published by the decoder after every tracker update
+#[derive(Default)]
+struct Snapshot {
+    now: Timestamp,
+    aircraft: HashMap<Icao, Aircraft>,
+    stats: Stats,
+    /// Wall clock time at timestamp 0, if known
+    start: Option<SystemTime>,
+}
+
+/// Serves the tracker state as JSON over HTTP, and aircraft tracks as GPX, KML or GeoJSON.
+///
+/// Requests are answered from a snapshot that is refreshed with [`HttpServer::publish`], so the decoder never waits
+/// on a client. The lock is only held to swap the snapshot in or to take a reference to it, never while answering.
+pub struct HttpServer {
+    snapshot: Arc<Mutex<Arc<Snapshot>>>,
+}
+
+impl HttpServer {
+    pub fn bind(addr: SocketAddr) -> anyhow::Result<Self> {
+        let server = Server::http(addr).map_err(|e| anyhow::anyhow!("{e}"))?;
+        let snapshot: Arc<Mutex<Arc<Snapshot>>> = Default::default();
+
+        let server_snapshot = snapshot.clone();
+        thread::Builder::new()
+            .name("HTTP server".to_string())
+            .spawn(move || {
+                for request in server.incoming_requests() {
+                    respond(request, &server_snapshot);
+                }
+            })?;
+
+        info!("HTTP server listening on {addr}");
+        Ok(HttpServer { snapshot })
+    }
+
+    /// Replace the state served to clients. Aircraft paths are shared with the tracker, only their latest positions
+    /// are copied
+    pub fn publish(
+        &self,
+        tracker: &Tracker,
+        stats: &Stats,
+        now: Timestamp,
+        start: Option<SystemTime>,
+    ) {
+        let snapshot = Snapshot {
+            now,
+            aircraft: tracker.aircrafts.clone(),
+            stats: stats.clone(),
+            start,
+        };
+
+        // The previous snapshot is freed after the lock is released
+        let previous = std::mem::replace(&mut *self.snapshot.lock().unwrap(), Arc::new(snapshot));
+        drop(previous);
+    }
+}
+
+fn respond(request: Request, snapshot: &Mutex<Arc<Snapshot>>) {
+    let (status, content_type, body) = if *request.method() == Method::Get {
+        let snapshot = snapshot.lock().unwrap().clone();
+        route(request.url(), &snapshot)
+    } else {
+        (405, JSON, error("method not allowed"))
+    };
+
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", content_type).unwrap())
+        .with_header(Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap());
+
+    if let Err(e) = request.respond(response) {
+        warn!("Failed to send HTTP response: {e}");
+    }
+}
+
+const JSON: &str = "application/json";
+
+/// Answer a GET request for `url`, returning the status code, content type and body
+fn route(url: &str, snapshot: &Snapshot) -> (u16, &'static str, String) {
+    let path = url.split('?').next().unwrap_or_default();
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+
+    let aircraft = |icao: &str| {
+        icao.parse::<Icao>()
+            .ok()
+            .and_then(|icao| snapshot.aircraft.get(&icao))
+    };
+    // `track.gpx` or `tracks.kml`, for the given file stem
+    let track_format = |file: &str, stem: &str| {
+        file.strip_prefix(stem)
+            .and_then(|extension| extension.strip_prefix('.'))
+            .and_then(TrackFormat::from_extension)
+    };
+    let tracks = |aircraft: Vec<&Aircraft>, format: TrackFormat| {
+        let start = snapshot.start.unwrap_or(UNIX_EPOCH);
+        (
+            200,
+            format.content_type(),
+            export::export_tracks(aircraft, format, start),
+        )
+    };
+
+    match segments[..] {
+        ["aircraft"] => {
+            let mut aircraft = snapshot
+                .aircraft
+                .values()
+                .map(|craft| AircraftDetail::new(craft, snapshot.now, &snapshot.stats.sources))
+                .collect::<Vec<_>>();
+            aircraft.sort_by(|a, b| a.icao.cmp(&b.icao));
+            ok(&aircraft)
+        }
+        ["aircraft", icao] => match aircraft(icao) {
+            Some(craft) => ok(&AircraftDetail::new(
+                craft,
+                snapshot.now,
+                &snapshot.stats.sources,
+            )),
+            None => not_found("unknown aircraft"),
+        },
+        ["aircraft", icao, "path"] => match aircraft(icao) {
+            Some(craft) => ok(&AircraftPath {
+                icao: craft.icao.to_string(),
+                path: craft.path.iter().map(|point| point.latlon()).collect(),
+            }),
+            None => not_found("unknown aircraft"),
+        },
+        ["aircraft", icao, file] if track_format(file, "track").is_some() => match aircraft(icao) {
+            Some(craft) => tracks(vec![craft], track_format(file, "track").unwrap()),
+            None => not_found("unknown aircraft"),
+        },
+        [file] if track_format(file, "tracks").is_some() => {
+            let mut aircraft = snapshot.aircraft.values().collect::<Vec<_>>();
+            aircraft.sort_by_key(|craft| craft.icao.to_string());
+            tracks(aircraft, track_format(file, "tracks").unwrap())
+        }
+        ["stats"] => ok(&snapshot.stats),
+        _ => not_found("not found"),
+    }
+}
+
+fn ok(value: &impl Serialize) -> (u16, &'static str, String) {
+    match serde_json::to_string(value) {
+        Ok(body) => (200, JSON, body),
+        Err(e) => (500, JSON, error(&e.to_string())),
+    }
+}
+
+fn not_found(message: &str) -> (u16, &'static str, String) {
+    (404, JSON, error(message))
+}
+
+fn error(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+#[derive(Serialize)]
+struct AircraftDetail {
+    icao: String,
+    callsign: Option<String>,
+    category: Option<&'static str>,
+    squawk: Option<String>,
+    on_ground: Option<bool>,
+    altitude_baro_ft: Option<i32>,
+    altitude_geom_ft: Option<i32>,
+    /// Last decoded position as (latitude, longitude)
+    position: Option<(f64, f64)>,
+    /// Position extrapolated from the last fix using the current velocity
+    position_interpolated: Option<(f64, f64)>,
+    speed_kts: Option<f64>,
+    heading: Option<f64>,
+    vertical_rate_fpm: Option<i32>,
+    /// Seconds since the last message
+    seen: f64,
+    /// Seconds since the last position fix
+    seen_pos: Option<f64>,
+    messages: u64,
+    rssi: f64,
+    path_length: usize,
+    /// Names of the receivers that picked up this aircraft
+    seen_by: Vec<String>,
+}
+
+impl AircraftDetail {
+    fn new(craft: &Aircraft, now: Timestamp, sources: &[SourceStats]) -> Self {
+        let seconds_since = |t: Timestamp| now.saturating_duration_since(t).as_secs_f64();
+
+        AircraftDetail {
+            icao: craft.icao.to_string(),
+            callsign: craft.callsign.map(|c| c.value.to_string()),
+            category: craft.category.map(|c| c.value.description()),
+            squawk: craft.squawk.map(|s| format!("{:04X}", s.value)),
+            on_ground: craft.on_ground.map(|g| g.value),
+            altitude_baro_ft: craft.altitude_baro.and_then(|a| a.value.feet()),
+            altitude_geom_ft: craft.altitude_geom.and_then(|a| a.value.feet()),
+            position: craft.latlong(),
+            position_interpolated: craft
+                .latlong()
+                .map(|_| (craft.latitude_interpolated, craft.longitude_interpolated)),
+            speed_kts: craft.speed_kts(),
+            heading: craft.heading(),
+            vertical_rate_fpm: craft.vertical_rate.map(|v| v.value),
+            seen: seconds_since(craft.last_seen),
+            seen_pos: craft.position.map(|p| seconds_since(p.updated)),
+            messages: craft.messages.total,
+            rssi: craft.rssi(),
+            path_length: craft.path.len(),
+            seen_by: craft
+                .seen_by
+                .keys()
+                .filter_map(|source| sources.get(source.0 as usize))
+                .map(|source| source.name.clone())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AircraftPath {
+    icao: String,
+    /// Every decoded position as (latitude, longitude), oldest first
+    path: Vec<(f64, f64)>,
+}
+
+#[test]
+fn test_route() {
+    use crate::aircraft::DataSource;
+
+    let icao = Icao::new(0x4840D6);
+    let mut craft = Aircraft::new(icao, Timestamp::default());
+    craft.set_position((52.25, 3.91), Timestamp::default(), DataSource::AdsB);
+
+    let snapshot = Snapshot {
+        aircraft: HashMap::from([(icao, craft)]),
+        ..Default::default()
+    };
+
+    let (status, content_type, body) = route("/aircraft", &snapshot);
+    assert_eq!((status, content_type), (200, JSON));
+    assert!(body.contains("\"icao\":\"4840D6\""));
+
+    assert_eq!(route("/aircraft/4840d6", &snapshot).0, 200);
+    assert_eq!(
+        route("/aircraft/4840D6/path", &snapshot),
+        (
+            200,
+            JSON,
+            r#"{"icao":"4840D6","path":[[52.25,3.91]]}"#.to_string()
+        )
+    );
+    assert_eq!(
+        route("/aircraft/4840D6/track.gpx", &snapshot).1,
+        "application/gpx+xml"
+    );
+    assert!(route("/tracks.kml", &snapshot).2.contains("<gx:Track>"));
+    assert_eq!(route("/aircraft/4840D6/track.txt", &snapshot).0, 404);
+    assert_eq!(route("/aircraft/123456", &snapshot).0, 404);
+    assert_eq!(route("/aircraft/nope", &snapshot).0, 404);
+    assert_eq!(route("/stats?pretty", &snapshot).0, 200);
+    assert_eq!(route("/", &snapshot).0, 404);
+}
@@ -0,0 +1,241 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    str::FromStr,
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    frame::{Frame, SourceId},
+    net::{
+        avr,
+        beast::{self, BeastReader},
+        sbs::{self, SbsMessage},
+    },
+    timestamp::Timestamp,
+};
+
+/// How long to wait before reconnecting to an input that went away
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Beast,
+    Avr,
+    Sbs,
+}
+
+impl Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputFormat::Beast => write!(f, "Beast"),
+            InputFormat::Avr => write!(f, "AVR"),
+            InputFormat::Sbs => write!(f, "SBS"),
+        }
+    }
+}
+
+/// An input given on the command line as `FORMAT:ADDRESS`, eg. `beast:192.168.1.10:30005` to connect,
+/// `avr:30001` to listen or `beast:capture.bin` to read a file
+#[derive(Debug, Clone)]
+pub struct InputSpec {
+    pub format: InputFormat,
+    pub address: String,
+}
+
+impl Display for InputSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.format.to_string().to_lowercase(),
+            self.address
+        )
+    }
+}
+
+impl FromStr for InputSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((format, address)) = s.split_once(':') else {
+            anyhow::bail!("expected FORMAT:ADDRESS, eg. beast:127.0.0.1:30005");
+        };
+
+        let format = match format.to_ascii_lowercase().as_str() {
+            "beast" => InputFormat::Beast,
+            "avr" => InputFormat::Avr,
+            "sbs" => InputFormat::Sbs,
+            _ => anyhow::bail!("unknown input format {format:?}, expected beast, avr or sbs"),
+        };
+
+        Ok(InputSpec {
+            format,
+            address: address.to_string(),
+        })
+    }
+}
+
+/// Something received from an input, along with the time it arrived
+#[derive(Debug)]
+pub enum Input {
+    /// A Mode S frame with its source set. Its timestamp is not set yet, the receiver's clock is unrelated to ours
+    Frame(Frame, SystemTime),
+    /// Aircraft state that was already decoded by the sender
+    Sbs(SourceId, SbsMessage, SystemTime),
+}
+
+/// Connect to a remote receiver, reconnecting whenever the connection drops
+pub fn connect(spec: InputSpec, source: SourceId, tx: Sender<Input>) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name(format!("{} input {}", spec.format, spec.address))
+        .spawn(move || {
+            loop {
+                match TcpStream::connect(&spec.address) {
+                    Ok(stream) => {
+                        info!("Connected to {} input {}", spec.format, spec.address);
+                        match read_stream(spec.format, source, stream, &tx) {
+                            Ok(()) => return,
+                            Err(e) => warn!("{} input {} lost: {e}", spec.format, spec.address),
+                        }
+                    }
+                    Err(e) => warn!(
+                        "Failed to connect to {} input {}: {e}",
+                        spec.format, spec.address
+                    ),
+                }
+
+                thread::sleep(RECONNECT_DELAY);
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Accept connections from receivers pushing frames to us. The address can be a bare port number.
+///
+/// All connections share the same source.
+pub fn listen(spec: InputSpec, source: SourceId, tx: Sender<Input>) -> anyhow::Result<()> {
+    let addr = match spec.address.parse::<u16>() {
+        Ok(port) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+        Err(_) => spec.address.parse()?,
+    };
+    let listener = TcpListener::bind(addr)?;
+    let format = spec.format;
+    info!("{format} input listening on {}", listener.local_addr()?);
+
+    thread::Builder::new()
+        .name(format!("{format} input server"))
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        error!("Failed to accept {format} input: {e}");
+                        continue;
+                    }
+                };
+
+                let peer = stream
+                    .peer_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default();
+                info!("{format} input connected from {peer}");
+                let tx = tx.clone();
+                let spawned = thread::Builder::new()
+                    .name(format!("{format} input {peer}"))
+                    .spawn(move || {
+                        if let Err(e) = read_stream(format, source, stream, &tx) {
+                            info!("{format} input {peer} disconnected: {e}");
+                        }
+                    });
+
+                if let Err(e) = spawned {
+                    error!("Failed to start {format} input: {e}");
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Read a capture of a Beast, AVR or SBS stream (eg. saved with `nc`) as fast as possible.
+///
+/// Frames are timestamped as they are read, the timing of the capture is not preserved.
+pub fn read_file(spec: InputSpec, source: SourceId, tx: Sender<Input>) -> anyhow::Result<()> {
+    let file = File::open(&spec.address)?;
+    thread::Builder::new()
+        .name(format!("{} file {}", spec.format, spec.address))
+        .spawn(move || {
+            if let Err(e) = read_stream(spec.format, source, file, &tx) {
+                info!("Stopped reading {} file {}: {e}", spec.format, spec.address);
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Parse everything coming in on `stream` and forward it. Returns `Ok` once the decoder has gone away
+fn read_stream(
+    format: InputFormat,
+    source: SourceId,
+    mut stream: impl Read,
+    tx: &Sender<Input>,
+) -> anyhow::Result<()> {
+    match format {
+        InputFormat::Beast => {
+            let mut reader = BeastReader::default();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut buf)?;
+                anyhow::ensure!(n > 0, "end of stream");
+
+                reader.push(&buf[..n]);
+                while let Some(message) = reader.next_message() {
+                    // Mode A/C replies don't carry an address and can't be tracked
+                    if message.message_type == beast::TYPE_MODE_AC {
+                        continue;
+                    }
+
+                    let mut frame = Frame::new(
+                        &message.data,
+                        Timestamp::default(),
+                        beast::decode_signal(message.signal),
+                    );
+                    frame.source = source;
+                    if tx.send(Input::Frame(frame, SystemTime::now())).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        InputFormat::Avr | InputFormat::Sbs => {
+            for line in BufReader::new(stream).lines() {
+                let line = line?;
+                let received = SystemTime::now();
+                let input = match format {
+                    InputFormat::Avr => avr::decode_line(&line).map(|(_, data)| {
+                        let mut frame = Frame::new(&data, Timestamp::default(), 0.0);
+                        frame.source = source;
+                        Input::Frame(frame, received)
+                    }),
+                    _ => {
+                        sbs::decode_line(&line).map(|message| Input::Sbs(source, message, received))
+                    }
+                };
+
+                if let Some(input) = input
+                    && tx.send(input).is_err()
+                {
+                    return Ok(());
+                }
+            }
+
+            anyhow::bail!("end of stream")
+        }
+    }
+}
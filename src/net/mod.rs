@@ -0,0 +1,159 @@
+use std::{
+    io::Write,
+    net::{Ipv4Addr, SocketAddr},
+    time::SystemTime,
+};
+
+use crate::{
+    aircraft::Aircraft, args::Args, decoder::Update, frame::Frame, json::unix_time,
+    mode_ac::ModeAcReply, tracker::TrackerEvent,
+};
+
+pub mod avr;
+pub mod beast;
+mod http;
+pub mod input;
+pub mod sbs;
+mod server;
+mod websocket;
+
+pub use http::HttpServer;
+pub use server::BroadcastServer;
+pub use websocket::{LiveEvent, WebSocketServer};
+
+/// All enabled frame outputs
+pub struct Outputs {
+    beast: Option<BroadcastServer>,
+    avr: Option<BroadcastServer>,
+    avr_stdout: bool,
+    avr_mlat: bool,
+    avr_corrected: bool,
+    sbs: Option<BroadcastServer>,
+    websocket: Option<WebSocketServer>,
+}
+
+impl Outputs {
+    pub fn new(args: &Args) -> anyhow::Result<Self> {
+        let bind = |name, port: Option<u16>| {
+            port.map(|port| {
+                BroadcastServer::bind(name, SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))
+            })
+            .transpose()
+        };
+
+        Ok(Outputs {
+            beast: bind("Beast", args.net_beast_port)?,
+            avr: bind("AVR", args.net_avr_port)?,
+            avr_stdout: args.avr_stdout,
+            avr_mlat: args.avr_mlat,
+            avr_corrected: args.avr_corrected,
+            sbs: bind("SBS", args.net_sbs_port)?,
+            websocket: args
+                .net_ws_port
+                .map(|port| WebSocketServer::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))))
+                .transpose()?,
+        })
+    }
+
+    /// Send a validated frame to every output
+    pub fn send_frame(&self, frame: &Frame) -> anyhow::Result<()> {
+        if let Some(beast) = &self.beast {
+            beast.broadcast(beast::encode_frame(frame));
+        }
+
+        let wants_avr = self.avr.is_some() || self.avr_stdout;
+        if wants_avr && (frame.corrected_bits == 0 || self.avr_corrected) {
+            let line = avr::encode_frame(frame, self.avr_mlat);
+            if self.avr_stdout {
+                std::io::stdout().lock().write_all(line.as_bytes())?;
+            }
+
+            if let Some(avr) = &self.avr {
+                avr.broadcast(line.into_bytes());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send a Mode A/C reply to the Beast output, the only format that carries them
+    pub fn send_mode_ac(&self, reply: &ModeAcReply) {
+        if let Some(beast) = &self.beast {
+            beast.broadcast(beast::encode(
+                reply.timestamp,
+                reply.signal_level,
+                &reply.beast_data(),
+            ));
+        }
+    }
+
+    /// Send a decoded aircraft update to every output
+    pub fn send_update(&self, update: &Update, frame: &Frame, craft: &Aircraft, time: SystemTime) {
+        if let Some(sbs) = &self.sbs
+            && let Some(line) = sbs::encode_update(update, craft, time)
+        {
+            sbs.broadcast(line.into_bytes());
+        }
+
+        if let Some(websocket) = &self.websocket {
+            let position = craft.latlong();
+            websocket.broadcast(
+                &LiveEvent::Message {
+                    icao: update.icao,
+                    kind: update.kind,
+                    downlink_format: frame.downlink_format(),
+                    frame: frame.data().iter().map(|b| format!("{b:02X}")).collect(),
+                    time: unix_time(time),
+                    rssi: frame.rssi_dbfs(),
+                    snr: frame.snr_db(),
+                },
+                position,
+            );
+
+            // Only report state that was changed by this frame
+            if let Some(fix) = craft.position
+                && fix.updated == frame.timestamp
+            {
+                let (lat, lon) = fix.value;
+                websocket.broadcast(
+                    &LiveEvent::Position {
+                        icao: update.icao,
+                        lat,
+                        lon,
+                        altitude_ft: craft.altitude().and_then(|a| a.value.feet()),
+                    },
+                    position,
+                );
+            }
+
+            if let Some(&(callsign, since)) = craft.callsign_history.last()
+                && since == frame.timestamp
+            {
+                websocket.broadcast(
+                    &LiveEvent::Callsign {
+                        icao: update.icao,
+                        callsign: callsign.to_string(),
+                    },
+                    position,
+                );
+            }
+        }
+    }
+
+    /// Report new and lost targets
+    pub fn send_tracker_event(&self, event: &TrackerEvent) {
+        if let Some(websocket) = &self.websocket {
+            match event {
+                // A new target can't have a position yet, it takes two messages to decode one
+                TrackerEvent::NewTarget(icao) => {
+                    websocket.broadcast(&LiveEvent::NewTarget { icao: *icao }, None)
+                }
+                // The position has usually timed out by now, fall back to the last one in the path
+                TrackerEvent::LostTarget(craft) => websocket.broadcast(
+                    &LiveEvent::LostTarget { icao: craft.icao },
+                    craft.latlong().or(craft.path.last().map(|p| p.latlon())),
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::{fmt::Write, time::SystemTime};
+
+use glam::DVec2;
+
+use crate::{
+    aircraft::{Aircraft, DataSource, Icao, Tracked},
+    altitude::{Altitude, AltitudeKind},
+    decoder::{Update, UpdateKind},
+    frame::SourceId,
+    identification::Callsign,
+    timestamp::{Timestamp, UtcDateTime},
+    tracker::Tracker,
+};
+
+/// Encode an aircraft update as a BaseStation `MSG` line, as served on port 30003.
+///
+/// Only the fields belonging to the message type are filled in, from the current aircraft state. Times are in UTC.
+/// Returns `None` for updates that don't have a BaseStation message type.
+pub fn encode_update(update: &Update, craft: &Aircraft, time: SystemTime) -> Option<String> {
+    let transmission_type = match update.kind {
+        UpdateKind::Identification => 1,
+        UpdateKind::SurfacePosition => 2,
+        UpdateKind::AirbornePosition => 3,
+        UpdateKind::AirborneVelocity => 4,
+        UpdateKind::SurveillanceAltitude => 5,
+        UpdateKind::SurveillanceIdentity => 6,
+        UpdateKind::AirToAir => 7,
+        UpdateKind::AllCallReply => 8,
+        UpdateKind::Other => return None,
+    };
+
+    let (date, time) = format_date_time(time);
+    let mut line = format!(
+        "MSG,{transmission_type},1,1,{},1,{date},{time},{date},{time},",
+        update.icao
+    );
+
+    let has = |types: &[u32]| types.contains(&transmission_type);
+    let flag = |value: Option<bool>| match value {
+        Some(true) => "-1",
+        Some(false) => "0",
+        None => "",
+    };
+
+    // Callsign
+    if has(&[1])
+        && let Some(callsign) = craft.callsign
+    {
+        line.push_str(callsign.value.as_str());
+    }
+    line.push(',');
+
+    // Altitude
+    if has(&[2, 3, 5, 6, 7])
+        && let Some(feet) = craft.altitude().and_then(|a| a.value.feet())
+    {
+        write!(line, "{feet}").unwrap();
+    }
+    line.push(',');
+
+    // Ground speed and track
+    if has(&[2, 4])
+        && let (Some(speed), Some(heading)) = (craft.speed_kts(), craft.heading())
+    {
+        write!(line, "{speed:.0},{heading:.0}").unwrap();
+    } else {
+        line.push(',');
+    }
+    line.push(',');
+
+    // Latitude and longitude
+    if has(&[2, 3])
+        && let Some((lat, lon)) = craft.latlong()
+    {
+        write!(line, "{lat:.5},{lon:.5}").unwrap();
+    } else {
+        line.push(',');
+    }
+    line.push(',');
+
+    // Vertical rate
+    if has(&[4])
+        && let Some(vertical_rate) = craft.vertical_rate
+    {
+        write!(line, "{}", vertical_rate.value).unwrap();
+    }
+    line.push(',');
+
+    // Squawk
+    if has(&[6])
+        && let Some(squawk) = craft.squawk
+    {
+        write!(line, "{:04X}", squawk.value).unwrap();
+    }
+    line.push(',');
+
+    let alert = has(&[3, 5, 6])
+        .then(|| craft.alert.map(|a| a.value))
+        .flatten();
+    let emergency = has(&[3, 6]).then(|| craft.emergency()).flatten();
+    let spi = has(&[3, 5, 6])
+        .then(|| craft.spi.map(|s| s.value))
+        .flatten();
+    let on_ground = craft.on_ground.map(|g| g.value);
+    write!(
+        line,
+        "{},{},{},{}\r\n",
+        flag(alert),
+        flag(emergency),
+        flag(spi),
+        flag(on_ground)
+    )
+    .unwrap();
+
+    Some(line)
+}
+
+/// The fields of a BaseStation `MSG` line. Empty fields are `None`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SbsMessage {
+    pub transmission_type: u8,
+    pub icao: Option<Icao>,
+    pub callsign: Option<Callsign>,
+    pub altitude: Option<i32>,
+    pub ground_speed: Option<f64>,
+    pub track: Option<f64>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub vertical_rate: Option<i32>,
+    pub squawk: Option<u16>,
+    pub alert: Option<bool>,
+    pub spi: Option<bool>,
+    pub on_ground: Option<bool>,
+}
+
+/// Parse a BaseStation line, returning `None` for anything that isn't a well-formed `MSG` line
+pub fn decode_line(line: &str) -> Option<SbsMessage> {
+    let fields = line.trim_end().split(',').collect::<Vec<_>>();
+    if fields.len() < 22 || fields[0] != "MSG" {
+        return None;
+    }
+
+    fn parse<T: std::str::FromStr>(field: &str) -> Option<T> {
+        field.trim().parse().ok()
+    }
+    let flag = |field: &str| match field.trim() {
+        "-1" | "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    };
+
+    Some(SbsMessage {
+        transmission_type: parse(fields[1])?,
+        icao: parse(fields[4]),
+        callsign: Callsign::new(fields[10].trim()),
+        altitude: parse(fields[11]),
+        ground_speed: parse(fields[12]),
+        track: parse(fields[13]),
+        latitude: parse(fields[14]),
+        longitude: parse(fields[15]),
+        vertical_rate: parse(fields[16]),
+        squawk: u16::from_str_radix(fields[17].trim(), 16).ok(),
+        alert: flag(fields[18]),
+        spi: flag(fields[20]),
+        on_ground: flag(fields[21]),
+    })
+}
+
+impl SbsMessage {
+    /// Apply the decoded state to the tracker, returning what kind of update it was
+    pub fn apply(&self, tracker: &mut Tracker, from: SourceId, now: Timestamp) -> Option<Update> {
+        let kind = match self.transmission_type {
+            1 => UpdateKind::Identification,
+            2 => UpdateKind::SurfacePosition,
+            3 => UpdateKind::AirbornePosition,
+            4 => UpdateKind::AirborneVelocity,
+            5 => UpdateKind::SurveillanceAltitude,
+            6 => UpdateKind::SurveillanceIdentity,
+            7 => UpdateKind::AirToAir,
+            8 => UpdateKind::AllCallReply,
+            _ => return None,
+        };
+        let source = if self.transmission_type <= 4 {
+            DataSource::AdsB
+        } else {
+            DataSource::Surveillance
+        };
+
+        let icao = self.icao?;
+        let craft = tracker.aircraft_reported(icao, from, now);
+
+        if let Some(callsign) = self.callsign {
+            craft.update_callsign(callsign, now, source);
+        }
+        if let Some(feet) = self.altitude {
+            let altitude = Altitude::Feet(AltitudeKind::Barometric, feet);
+            craft.altitude_baro = Some(Tracked::new(altitude, now, source));
+        }
+        if let (Some(speed), Some(track)) = (self.ground_speed, self.track) {
+            let track = track.to_radians();
+            let velocity = DVec2::new(speed * track.sin(), speed * track.cos());
+            craft.velocity_kts = Some(Tracked::new(velocity, now, source));
+        }
+        if let (Some(lat), Some(lon)) = (self.latitude, self.longitude) {
+            craft.set_position((lat, lon), now, source);
+        }
+        if let Some(vertical_rate) = self.vertical_rate {
+            craft.vertical_rate = Some(Tracked::new(vertical_rate, now, source));
+        }
+        if let Some(squawk) = self.squawk {
+            craft.squawk = Some(Tracked::new(squawk, now, source));
+        }
+        if let Some(alert) = self.alert {
+            craft.alert = Some(Tracked::new(alert, now, source));
+        }
+        if let Some(spi) = self.spi {
+            craft.spi = Some(Tracked::new(spi, now, source));
+        }
+        if let Some(on_ground) = self.on_ground {
+            craft.on_ground = Some(Tracked::new(on_ground, now, source));
+        }
+
+        Some(Update { icao, kind })
+    }
+}
+
+/// Format a time as `YYYY/MM/DD` and `HH:MM:SS.mmm` (UTC)
+fn format_date_time(time: SystemTime) -> (String, String) {
+    let utc = UtcDateTime::from(time);
+    (
+        format!("{:04}/{:02}/{:02}", utc.year, utc.month, utc.day),
+        format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            utc.hour, utc.minute, utc.second, utc.millisecond
+        ),
+    )
+}
+
+#[test]
+fn test_format_date_time() {
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_227_916_098_611);
+    assert_eq!(
+        format_date_time(time),
+        ("2008/11/28".to_string(), "23:48:18.611".to_string())
+    );
+}
+
+#[test]
+fn test_encode_airborne_position() {
+    use crate::{
+        aircraft::{DataSource, Icao, Tracked},
+        altitude::{Altitude, AltitudeKind},
+        timestamp::Timestamp,
+    };
+
+    let now = Timestamp::default();
+    let mut craft = Aircraft::new(Icao::new(0x4840D6), now);
+    craft.altitude_baro = Some(Tracked::new(
+        Altitude::Feet(AltitudeKind::Barometric, 38000),
+        now,
+        DataSource::AdsB,
+    ));
+    craft.position = Some(Tracked::new((52.25720, 3.91937), now, DataSource::AdsB));
+    craft.on_ground = Some(Tracked::new(false, now, DataSource::AdsB));
+
+    let update = Update {
+        icao: craft.icao,
+        kind: UpdateKind::AirbornePosition,
+    };
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_227_916_098_611);
+    assert_eq!(
+        encode_update(&update, &craft, time).unwrap(),
+        "MSG,3,1,1,4840D6,1,2008/11/28,23:48:18.611,2008/11/28,23:48:18.611,,38000,,,52.25720,3.91937,,,,,,0\r\n"
+    );
+}
+
+#[test]
+fn test_decode_line() {
+    let line = "MSG,3,1,1,4840D6,1,2008/11/28,23:48:18.611,2008/11/28,23:48:18.611,,38000,,,52.25720,3.91937,,,,,,0\r\n";
+    let message = decode_line(line).unwrap();
+    assert_eq!(message.icao, Some(Icao::new(0x4840D6)));
+    assert_eq!(message.altitude, Some(38000));
+    assert_eq!(message.latitude, Some(52.2572));
+    assert_eq!(message.on_ground, Some(false));
+    assert_eq!(message.callsign, None);
+
+    let mut tracker = Tracker::new(Default::default());
+    let update = message
+        .apply(&mut tracker, SourceId::default(), Timestamp::default())
+        .unwrap();
+    assert_eq!(update.kind, UpdateKind::AirbornePosition);
+    assert_eq!(
+        tracker.aircrafts[&update.icao].latlong(),
+        Some((52.2572, 3.91937))
+    );
+
+    assert_eq!(decode_line("STA,,5,179,400AE7,10103,2008/11/28"), None);
+}
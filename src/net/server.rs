@@ -0,0 +1,132 @@
+use std::{
+    io::Write,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread,
+};
+
+/// Number of messages that can be queued for a single client before we start dropping them
+const CLIENT_QUEUE_LENGTH: usize = 1024;
+
+type Clients = Arc<Mutex<Vec<ClientQueue<Arc<[u8]>>>>>;
+
+/// The queue of a connected client, and how many messages it has missed since it fell behind
+pub(super) struct ClientQueue<T> {
+    name: &'static str,
+    peer: SocketAddr,
+    tx: SyncSender<T>,
+    dropped: u64,
+}
+
+impl<T> ClientQueue<T> {
+    /// `name` is the kind of client, for logging. The client thread receives the messages from the returned end.
+    pub(super) fn new(name: &'static str, peer: SocketAddr) -> (Self, Receiver<T>) {
+        let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE_LENGTH);
+        let queue = ClientQueue {
+            name,
+            peer,
+            tx,
+            dropped: 0,
+        };
+        (queue, rx)
+    }
+
+    /// Queue `message` unless the queue is full, returning whether the client is still connected.
+    ///
+    /// A client that falls behind is only logged when it starts dropping messages and when it catches up again
+    pub(super) fn try_send(&mut self, message: T) -> bool {
+        match self.tx.try_send(message) {
+            Ok(()) => {
+                if self.dropped > 0 {
+                    info!(
+                        "{} client {} caught up after {} dropped messages",
+                        self.name, self.peer, self.dropped
+                    );
+                    self.dropped = 0;
+                }
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                if self.dropped == 0 {
+                    warn!(
+                        "{} client {} is too slow, dropping messages",
+                        self.name, self.peer
+                    );
+                }
+                self.dropped += 1;
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// A TCP server that sends the same stream of bytes to every connected client.
+///
+/// Every client gets its own writer thread, so slow clients can never stall the decoder. Messages for clients that
+/// can't keep up are dropped instead.
+pub struct BroadcastServer {
+    clients: Clients,
+}
+
+impl BroadcastServer {
+    pub fn bind(name: &'static str, addr: SocketAddr) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let clients: Clients = Default::default();
+
+        let accept_clients = clients.clone();
+        thread::Builder::new()
+            .name(format!("{name} server"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => match spawn_client_writer(name, stream) {
+                            Ok(queue) => accept_clients.lock().unwrap().push(queue),
+                            Err(e) => error!("Failed to start {name} client: {e}"),
+                        },
+                        Err(e) => error!("Failed to accept {name} client: {e}"),
+                    }
+                }
+            })?;
+
+        info!("{name} server listening on {local_addr}");
+        Ok(BroadcastServer { clients })
+    }
+
+    /// Queue `data` to be sent to every connected client, dropping it for clients that can't keep up
+    pub fn broadcast(&self, data: impl Into<Arc<[u8]>>) {
+        let data = data.into();
+        self.clients
+            .lock()
+            .unwrap()
+            .retain_mut(|queue| queue.try_send(data.clone()));
+    }
+}
+
+/// Start sending queued messages to `stream`, returning the queue of the client
+fn spawn_client_writer(
+    name: &'static str,
+    mut stream: TcpStream,
+) -> anyhow::Result<ClientQueue<Arc<[u8]>>> {
+    let peer = stream.peer_addr()?;
+    stream.set_nodelay(true)?;
+    info!("{name} client connected from {peer}");
+
+    let (queue, rx) = ClientQueue::<Arc<[u8]>>::new(name, peer);
+    thread::Builder::new()
+        .name(format!("{name} client {peer}"))
+        .spawn(move || {
+            for data in rx {
+                if let Err(e) = stream.write_all(&data) {
+                    info!("{name} client {peer} disconnected: {e}");
+                    break;
+                }
+            }
+        })?;
+
+    Ok(queue)
+}
@@ -0,0 +1,283 @@
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, TryRecvError},
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message, WebSocket};
+
+use super::server::ClientQueue;
+use crate::{aircraft::Icao, decoder::UpdateKind};
+
+/// How often client threads check for subscription changes while no events are queued
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// An event pushed to WebSocket clients, serialized as JSON with a `type` field
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// A decoded message from an aircraft
+    Message {
+        icao: Icao,
+        kind: UpdateKind,
+        downlink_format: u8,
+        /// Raw frame as hex
+        frame: String,
+        /// Unix time the frame was received at
+        time: f64,
+        /// Signal strength in dBFS
+        rssi: f64,
+        /// How far the frame stood above the noise floor in dB, for frames demodulated here
+        snr: Option<f64>,
+    },
+    /// A new position fix
+    Position {
+        icao: Icao,
+        lat: f64,
+        lon: f64,
+        altitude_ft: Option<i32>,
+    },
+    /// The aircraft started using a different callsign
+    Callsign {
+        icao: Icao,
+        callsign: String,
+    },
+    NewTarget {
+        icao: Icao,
+    },
+    LostTarget {
+        icao: Icao,
+    },
+}
+
+impl LiveEvent {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            LiveEvent::Message { .. } => EventType::Message,
+            LiveEvent::Position { .. } => EventType::Position,
+            LiveEvent::Callsign { .. } => EventType::Callsign,
+            LiveEvent::NewTarget { .. } => EventType::NewTarget,
+            LiveEvent::LostTarget { .. } => EventType::LostTarget,
+        }
+    }
+
+    pub fn icao(&self) -> Icao {
+        match *self {
+            LiveEvent::Message { icao, .. }
+            | LiveEvent::Position { icao, .. }
+            | LiveEvent::Callsign { icao, .. }
+            | LiveEvent::NewTarget { icao }
+            | LiveEvent::LostTarget { icao } => icao,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    Message,
+    Position,
+    Callsign,
+    NewTarget,
+    LostTarget,
+}
+
+/// Filters sent by a client as a JSON text message, eg.
+/// `{"icao": ["4840D6"], "bbox": [51.0, 3.0, 54.0, 7.5], "events": ["position"], "kinds": ["airborne_position"]}`.
+///
+/// Every field is optional, empty filters match everything. A new subscription replaces the previous one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Subscription {
+    /// Only send events for these aircraft
+    pub icao: Vec<Icao>,
+    /// Only send events for aircraft whose last known position is inside `[min_lat, min_lon, max_lat, max_lon]`
+    pub bbox: Option<[f64; 4]>,
+    /// Only send these kinds of events
+    pub events: Vec<EventType>,
+    /// Only send `message` events for these kinds of messages
+    pub kinds: Vec<UpdateKind>,
+}
+
+impl Subscription {
+    /// `position` is the last known position of the aircraft the event is about
+    pub fn matches(&self, event: &LiveEvent, position: Option<(f64, f64)>) -> bool {
+        if !self.icao.is_empty() && !self.icao.contains(&event.icao()) {
+            return false;
+        }
+
+        if !self.events.is_empty() && !self.events.contains(&event.event_type()) {
+            return false;
+        }
+
+        if let LiveEvent::Message { kind, .. } = event
+            && !self.kinds.is_empty()
+            && !self.kinds.contains(kind)
+        {
+            return false;
+        }
+
+        if let Some([min_lat, min_lon, max_lat, max_lon]) = self.bbox {
+            let Some((lat, lon)) = position else {
+                return false;
+            };
+
+            if !(min_lat..=max_lat).contains(&lat) || !(min_lon..=max_lon).contains(&lon) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+struct Client {
+    subscription: Arc<Mutex<Subscription>>,
+    queue: ClientQueue<Arc<str>>,
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+/// A WebSocket server pushing [`LiveEvent`]s to every client whose subscription matches them
+pub struct WebSocketServer {
+    clients: Clients,
+}
+
+impl WebSocketServer {
+    pub fn bind(addr: SocketAddr) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let clients: Clients = Default::default();
+
+        let accept_clients = clients.clone();
+        thread::Builder::new()
+            .name("WebSocket server".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            if let Err(e) = spawn_client(stream, accept_clients.clone()) {
+                                error!("Failed to start WebSocket client: {e}");
+                            }
+                        }
+                        Err(e) => error!("Failed to accept WebSocket client: {e}"),
+                    }
+                }
+            })?;
+
+        info!("WebSocket server listening on {local_addr}");
+        Ok(WebSocketServer { clients })
+    }
+
+    /// Queue `event` for every client subscribed to it. `position` is the last known position of the aircraft.
+    pub fn broadcast(&self, event: &LiveEvent, position: Option<(f64, f64)>) {
+        let mut json: Option<Arc<str>> = None;
+        self.clients.lock().unwrap().retain_mut(|client| {
+            if !client.subscription.lock().unwrap().matches(event, position) {
+                return true;
+            }
+
+            let json =
+                json.get_or_insert_with(|| serde_json::to_string(event).unwrap_or_default().into());
+            client.queue.try_send(json.clone())
+        });
+    }
+}
+
+fn spawn_client(stream: TcpStream, clients: Clients) -> anyhow::Result<()> {
+    let peer = stream.peer_addr()?;
+
+    thread::Builder::new()
+        .name(format!("WebSocket client {peer}"))
+        .spawn(move || {
+            let mut socket = match tungstenite::accept(stream) {
+                Ok(socket) => socket,
+                Err(e) => {
+                    warn!("WebSocket handshake with {peer} failed: {e}");
+                    return;
+                }
+            };
+            info!("WebSocket client connected from {peer}");
+
+            let subscription: Arc<Mutex<Subscription>> = Default::default();
+            let (queue, rx) = ClientQueue::new("WebSocket", peer);
+            clients.lock().unwrap().push(Client {
+                subscription: subscription.clone(),
+                queue,
+            });
+
+            if let Err(e) = serve_client(&mut socket, &subscription, rx) {
+                info!("WebSocket client {peer} disconnected: {e}");
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Forward queued events to the client and apply any subscriptions it sends, until it disconnects
+fn serve_client(
+    socket: &mut WebSocket<TcpStream>,
+    subscription: &Mutex<Subscription>,
+    rx: Receiver<Arc<str>>,
+) -> anyhow::Result<()> {
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => match serde_json::from_str::<Subscription>(&text) {
+                Ok(new) => *subscription.lock().unwrap() = new,
+                Err(e) => socket.send(Message::text(
+                    serde_json::json!({ "type": "error", "error": e.to_string() }).to_string(),
+                ))?,
+            },
+            Ok(Message::Close(_)) => anyhow::bail!("connection closed"),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        loop {
+            match rx.try_recv() {
+                Ok(event) => socket.send(Message::text(event.as_ref()))?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_subscription() {
+    let subscription: Subscription =
+        serde_json::from_str(r#"{"icao": ["4840D6"], "bbox": [51.0, 3.0, 54.0, 7.5]}"#).unwrap();
+    let event = LiveEvent::NewTarget {
+        icao: Icao::new(0x4840D6),
+    };
+
+    assert!(subscription.matches(&event, Some((52.25, 3.91))));
+    assert!(!subscription.matches(&event, Some((48.0, 3.91))));
+    assert!(!subscription.matches(&event, None));
+    assert!(!subscription.matches(
+        &LiveEvent::NewTarget {
+            icao: Icao::new(0x123456)
+        },
+        Some((52.25, 3.91))
+    ));
+
+    let subscription: Subscription =
+        serde_json::from_str(r#"{"events": ["message"], "kinds": ["airborne_velocity"]}"#).unwrap();
+    assert!(!subscription.matches(&event, None));
+    assert_eq!(
+        serde_json::to_string(&event).unwrap(),
+        r#"{"type":"new_target","icao":"4840D6"}"#
+    );
+}
@@ -0,0 +1,320 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    aircraft::Tracked,
+    args::Args,
+    decoder::{self, Decoded},
+    dedup::Deduplicator,
+    export::{self, TrackFormat},
+    frame::Frame,
+    json::JsonWriter,
+    mode_ac::ModeAcReply,
+    net::{HttpServer, Outputs, input::Input},
+    recording::FrameRecorder,
+    stats::Stats,
+    timestamp::{SampleClock, Timestamp},
+    tracker::{Tracker, TrackerConfig, TrackerEvent},
+};
+
+/// How often aircraft are expired, interpolated and published
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Squawks older than this aren't matched against Mode A/C replies anymore
+const MODE_AC_SQUAWK_AGE: Duration = Duration::from_secs(60);
+
+/// Everything that happens to a frame after demodulation: validation, decoding, tracking and the outputs.
+///
+/// Frames from the SDR and from network inputs all go through here.
+pub struct Pipeline {
+    pub tracker: Tracker,
+    pub stats: Stats,
+    pub clock: SampleClock,
+    dedup: Deduplicator,
+    outputs: Outputs,
+    json_writer: Option<JsonWriter>,
+    http: Option<HttpServer>,
+    recorder: Option<FrameRecorder>,
+    export_tracks: Option<PathBuf>,
+    export_formats: Vec<TrackFormat>,
+    correlate_mode_ac: bool,
+    rec: rerun::RecordingStream,
+    last_tick: Timestamp,
+}
+
+impl Pipeline {
+    pub fn new(args: &Args, clock: SampleClock) -> anyhow::Result<Self> {
+        let start = clock.wall_clock(Timestamp::default());
+        Ok(Pipeline {
+            tracker: Tracker::new(TrackerConfig {
+                position_timeout: Duration::from_secs(args.position_timeout),
+                velocity_timeout: Duration::from_secs(args.velocity_timeout),
+                target_timeout: Duration::from_secs(args.target_timeout),
+                receiver: args.lat.zip(args.lon),
+            }),
+            stats: Stats::default(),
+            clock,
+            dedup: Deduplicator::new(Duration::from_millis(args.dedup_window_ms)),
+            outputs: Outputs::new(args)?,
+            json_writer: args
+                .write_json
+                .as_ref()
+                .map(|dir| JsonWriter::new(dir, Duration::from_secs_f64(args.write_json_every)))
+                .transpose()?,
+            http: args
+                .net_http_port
+                .map(|port| HttpServer::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))))
+                .transpose()?,
+            recorder: args
+                .record
+                .as_ref()
+                .map(|path| FrameRecorder::create(path, start))
+                .transpose()?,
+            export_tracks: args.export_tracks.clone(),
+            export_formats: args.export_format.clone(),
+            correlate_mode_ac: args.mode_ac_correlate,
+            rec: rerun::RecordingStreamBuilder::new("stribog").connect_grpc()?,
+            last_tick: Timestamp::default(),
+        })
+    }
+
+    /// Expire, interpolate and publish the tracked aircraft, if it's been long enough since the last time
+    pub fn tick(&mut self, now: Timestamp) -> anyhow::Result<()> {
+        let tick = now.saturating_duration_since(self.last_tick);
+        if tick <= TICK_INTERVAL {
+            return Ok(());
+        }
+
+        self.set_rerun_time(now);
+        self.tracker.expire(now);
+        self.dedup.expire(now);
+        for event in self.tracker.drain_events() {
+            self.outputs.send_tracker_event(&event);
+            match event {
+                TrackerEvent::NewTarget(icao) => {
+                    info!("New target {icao}");
+                    self.stats.tracks_new += 1;
+                }
+                TrackerEvent::LostTarget(craft) => {
+                    self.stats.tracks_lost += 1;
+                    info!(
+                        "Lost target {} after {:.0?} ({} positions)",
+                        craft.icao,
+                        craft.last_seen.saturating_duration_since(craft.first_seen),
+                        craft.path.len()
+                    );
+                    if let Some(dir) = &self.export_tracks
+                        && !craft.path.is_empty()
+                    {
+                        let start = self
+                            .clock
+                            .wall_clock(Timestamp::default())
+                            .unwrap_or(UNIX_EPOCH);
+                        for &format in &self.export_formats {
+                            if let Err(e) = export::write_track(dir, &craft, format, start) {
+                                warn!("Failed to export the track of {}: {e}", craft.icao);
+                            }
+                        }
+                    }
+                    self.rec.log(
+                        format!("world/plane/{}", craft.icao),
+                        &rerun::Clear::recursive(),
+                    )?;
+                }
+            }
+        }
+
+        for (_, craft) in self.tracker.aircrafts.iter_mut() {
+            if craft.latlong().is_none() {
+                continue;
+            }
+
+            let Some(Tracked {
+                value: velocity_kts,
+                ..
+            }) = craft.velocity_kts
+            else {
+                continue;
+            };
+
+            let d_meters_per_sec = velocity_kts * 0.5144444444;
+            let d_meters_per_tick = d_meters_per_sec * tick.as_secs_f64();
+            let [dx, dy] = d_meters_per_tick.to_array();
+            const R: f64 = 6_371_000.0;
+            let dlat = dy / R;
+            let dlon = dx / (R * craft.latitude_interpolated.to_radians().cos());
+
+            craft.latitude_interpolated += dlat.to_degrees();
+            craft.longitude_interpolated += dlon.to_degrees();
+
+            craft.log_rerun(&self.rec)?;
+        }
+        // send interpolated samples for all aircrafts to rerun for visualization sake
+
+        if let Some(json_writer) = &mut self.json_writer {
+            let wall_clock = self.clock.wall_clock(now).unwrap_or_else(SystemTime::now);
+            json_writer.update(&self.tracker, &self.stats, now, wall_clock)?;
+        }
+
+        if let Some(http) = &self.http {
+            let start = self.clock.wall_clock(Timestamp::default());
+            http.publish(&self.tracker, &self.stats, now, start);
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.flush()?;
+        }
+
+        self.last_tick = now;
+        Ok(())
+    }
+
+    /// Validate a demodulated frame, decode it into the tracker and send it to the outputs.
+    ///
+    /// Returns whether the frame was valid
+    pub fn handle_frame(&mut self, mut frame: Frame) -> anyhow::Result<bool> {
+        // Surveillance replies overlay the parity with the aircraft address, these are validated by the tracker
+        if !decoder::has_address_parity(frame.downlink_format()) {
+            if !frame.fix_parity() {
+                self.stats.bad_crc += 1;
+                self.stats.source_mut(frame.source).bad_crc += 1;
+                return Ok(false);
+            }
+
+            if frame.corrected_bits > 0 {
+                self.stats.messages_corrected += 1;
+            }
+            debug!(
+                "CRC ok, *{}",
+                frame
+                    .data()
+                    .iter()
+                    .map(|&b| format!("{:02x}", b))
+                    .collect::<Vec<_>>()
+                    .join("")
+            );
+        }
+
+        if self.dedup.is_duplicate(&frame) {
+            self.stats.duplicates += 1;
+            self.stats.source_mut(frame.source).duplicates += 1;
+
+            // Still credit the receiver with seeing the aircraft
+            let craft = frame
+                .icao()
+                .and_then(|icao| self.tracker.aircrafts.get_mut(&icao));
+            let verified = craft.is_some() || !decoder::has_address_parity(frame.downlink_format());
+            if let Some(craft) = craft {
+                craft.seen_by.insert(frame.source, frame.timestamp);
+            }
+
+            // Duplicates are recorded too, so replays deduplicate the same way
+            if let Some(recorder) = &mut self.recorder
+                && verified
+            {
+                recorder.record(&frame)?;
+            }
+            return Ok(true);
+        }
+
+        self.set_rerun_time(frame.timestamp);
+        let decoded = decoder::handle_frame(&frame, &mut self.tracker, &self.rec)?;
+        if matches!(decoded, Decoded::Unverified) {
+            self.stats.unverified += 1;
+            self.stats.source_mut(frame.source).unverified += 1;
+            return Ok(false);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&frame)?;
+        }
+        self.stats.messages += 1;
+        self.stats.source_mut(frame.source).messages += 1;
+        if frame.noise_floor.is_some() {
+            self.stats.add_signal(frame.signal_level);
+        }
+
+        self.outputs.send_frame(&frame)?;
+        if let Decoded::Update(update) = decoded
+            && let Some(craft) = self.tracker.aircrafts.get(&update.icao)
+        {
+            let time = self
+                .clock
+                .wall_clock(frame.timestamp)
+                .unwrap_or_else(SystemTime::now);
+            self.outputs.send_update(&update, &frame, craft, time);
+        }
+
+        Ok(true)
+    }
+
+    /// Count a Mode A/C reply and send it to the outputs, matching its code against the tracked squawks if enabled
+    pub fn handle_mode_ac(&mut self, reply: &ModeAcReply) {
+        self.stats.mode_ac += 1;
+        self.outputs.send_mode_ac(reply);
+        if !self.correlate_mode_ac {
+            return;
+        }
+
+        // Codes shared by several aircraft (eg. 7000 or 2000) can't be attributed
+        let code = reply.mode_a();
+        let mut squawking = self.tracker.aircrafts.values().filter(|craft| {
+            craft.squawk.is_some_and(|squawk| {
+                squawk.value == code
+                    && reply.timestamp.saturating_duration_since(squawk.updated)
+                        < MODE_AC_SQUAWK_AGE
+            })
+        });
+        match (squawking.next(), squawking.next()) {
+            (Some(craft), None) => {
+                self.stats.mode_ac_matched += 1;
+                debug!("Mode A {code:04X} from {}", craft.icao);
+            }
+            _ => debug!(
+                "Mode A/C {code:04X} (altitude {:?} ft) not matched",
+                reply.altitude_ft()
+            ),
+        }
+    }
+
+    /// Handle something received from a network input, timestamping it on our own clock
+    pub fn handle_input(&mut self, input: Input) -> anyhow::Result<()> {
+        match input {
+            Input::Frame(mut frame, received) => {
+                frame.timestamp = self.timestamp_of(received);
+                self.handle_frame(frame)?;
+                Ok(())
+            }
+            Input::Sbs(source, message, received) => {
+                let now = self.timestamp_of(received);
+                if message.apply(&mut self.tracker, source, now).is_some() {
+                    self.stats.messages += 1;
+                    self.stats.source_mut(source).messages += 1;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Timestamp for something that arrived at `time`, or the latest sample time without a wall clock
+    pub fn timestamp_of(&self, time: SystemTime) -> Timestamp {
+        self.clock
+            .timestamp_of(time)
+            .unwrap_or_else(|| self.clock.timestamp_at(0))
+    }
+
+    /// Log to rerun on the wall clock if we have one, otherwise on the sample clock
+    fn set_rerun_time(&self, now: Timestamp) {
+        match self.clock.wall_clock(now) {
+            Some(time) => self.rec.set_timestamp_secs_since_epoch(
+                "time",
+                time.duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64(),
+            ),
+            None => self.rec.set_duration_secs("sample_time", now.as_secs_f64()),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Frame logs: every validated frame as a line of JSON, preceded by a header line. Address/parity frames are only
+//! validated by the tracker, so the ones from aircraft it doesn't know are left out.
+//!
+//! ```text
+//! {"version":1,"start_ms":1227916098611}
+//! {"t":1200000,"signal":0.25,"noise":0.0004,"corrected":0,"frame":"8D4840D6202CC371C32CE0576098"}
+//! ```
+//!
+//! `t` is the frame timestamp in 12 MHz ticks since the start of reception, `start_ms` the wall clock time at `t = 0`
+//! in Unix milliseconds, if it was known. `signal` and `noise` are powers relative to full scale, `noise` is only
+//! there for frames demodulated from the SDR.
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Lines, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{frame::Frame, net::avr, timestamp::Timestamp};
+
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    start_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedFrame {
+    t: u64,
+    signal: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    noise: Option<f32>,
+    corrected: u8,
+    frame: String,
+}
+
+/// Appends frames to a log file
+pub struct FrameRecorder {
+    out: BufWriter<File>,
+}
+
+impl FrameRecorder {
+    /// Create a new log. `start` is the wall clock time at timestamp 0
+    pub fn create(path: impl AsRef<Path>, start: Option<SystemTime>) -> anyhow::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(
+            &mut out,
+            &Header {
+                version: VERSION,
+                start_ms: start.map(|start| {
+                    start
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64
+                }),
+            },
+        )?;
+        out.write_all(b"\n")?;
+
+        Ok(FrameRecorder { out })
+    }
+
+    pub fn record(&mut self, frame: &Frame) -> anyhow::Result<()> {
+        let mut hex = String::with_capacity(28);
+        for b in frame.data() {
+            write!(hex, "{b:02X}").unwrap();
+        }
+
+        serde_json::to_writer(
+            &mut self.out,
+            &RecordedFrame {
+                t: frame.timestamp.ticks(),
+                signal: frame.signal_level,
+                noise: frame.noise_floor,
+                corrected: frame.corrected_bits,
+                frame: hex,
+            },
+        )?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Reads frames back from a log file
+pub struct FrameLog {
+    lines: Lines<BufReader<File>>,
+    /// Wall clock time at timestamp 0, if it was known while recording
+    pub start: Option<SystemTime>,
+}
+
+impl FrameLog {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let Some(header) = lines.next() else {
+            anyhow::bail!("frame log is empty");
+        };
+
+        let header: Header = serde_json::from_str(&header?)?;
+        anyhow::ensure!(
+            header.version == VERSION,
+            "unsupported frame log version {}",
+            header.version
+        );
+
+        Ok(FrameLog {
+            lines,
+            start: header
+                .start_ms
+                .map(|start| UNIX_EPOCH + Duration::from_millis(start)),
+        })
+    }
+}
+
+impl Iterator for FrameLog {
+    type Item = anyhow::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+
+        Some(
+            serde_json::from_str(&line)
+                .map_err(Into::into)
+                .and_then(|recorded: RecordedFrame| {
+                    let Some(data) = avr::decode_hex(&recorded.frame) else {
+                        anyhow::bail!("invalid frame {:?}", recorded.frame);
+                    };
+
+                    let mut frame =
+                        Frame::new(&data, Timestamp::from_ticks(recorded.t), recorded.signal);
+                    frame.corrected_bits = recorded.corrected;
+                    frame.noise_floor = recorded.noise;
+                    Ok(frame)
+                }),
+        )
+    }
+}
+
+#[test]
+fn test_recording_round_trip() {
+    let path = std::env::temp_dir().join(format!("stribog-test-{}.jsonl", std::process::id()));
+    let start = UNIX_EPOCH + Duration::from_millis(1_227_916_098_611);
+    let data = [
+        0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+    ];
+    let mut frame = Frame::new(&data, Timestamp::from_ticks(1_200_000), 0.25);
+    frame.corrected_bits = 1;
+    frame.noise_floor = Some(0.0004);
+
+    let mut recorder = FrameRecorder::create(&path, Some(start)).unwrap();
+    recorder.record(&frame).unwrap();
+    recorder.flush().unwrap();
+
+    let mut log = FrameLog::open(&path).unwrap();
+    assert_eq!(log.start, Some(start));
+    let replayed = log.next().unwrap().unwrap();
+    assert_eq!(replayed.data(), &data);
+    assert_eq!(replayed.timestamp, frame.timestamp);
+    assert_eq!(replayed.signal_level, 0.25);
+    assert_eq!(replayed.corrected_bits, 1);
+    assert_eq!(replayed.noise_floor, Some(0.0004));
+    assert!(log.next().is_none());
+
+    std::fs::remove_file(path).unwrap();
+}
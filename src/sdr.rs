@@ -0,0 +1,484 @@
+//! Reception from the RTL-SDR or an IQ file, spread over threads so the USB bus is always drained.
+//!
+//! A reader thread only copies buffers off the dongle into a bounded queue, dropping them (and counting the drops)
+//! when the demodulators fall behind. Buffers read from a file are never dropped, the reader waits for the
+//! demodulators instead. Demodulation workers turn each buffer into frames, and the decoder receives the results in
+//! the order the samples were read.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::Read,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError},
+    },
+    thread,
+    time::Duration,
+};
+
+use rtlsdr_rs::{RtlSdr, TunerGain};
+
+use crate::{
+    decoder,
+    demod::Demodulator,
+    frame::{Frame, SourceId},
+    magnitude::{self, Conversion, IqFormat, MagnitudeConverter},
+    mode_ac::{ModeAcDemodulator, ModeAcReply},
+    timestamp::Timestamp,
+    tracker::KnownAddresses,
+};
+
+const DEFAULT_BUF_LENGTH: usize = 16 * 16384;
+// const DEFAULT_BUF_LENGTH: usize = 2_500_000;
+
+const DEVICE_INDEX: usize = 0;
+
+/// Number of buffers that can wait for a demodulator before the reader starts dropping them
+const QUEUE_LENGTH: usize = 16;
+
+/// Where the IQ samples come from
+#[derive(Debug, Clone)]
+pub enum Samples {
+    /// The first RTL-SDR dongle, tuned to 1090 MHz
+    Sdr,
+    /// A file of raw samples, demodulated as fast as possible
+    File(PathBuf, IqFormat),
+}
+
+impl Samples {
+    fn format(&self) -> IqFormat {
+        match self {
+            Samples::Sdr => IqFormat::Cu8,
+            Samples::File(_, format) => *format,
+        }
+    }
+}
+
+impl fmt::Display for Samples {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Samples::Sdr => write!(f, "sdr"),
+            Samples::File(path, _) => write!(f, "iq:{}", path.display()),
+        }
+    }
+}
+
+/// An opened [`Samples`]
+enum Reader {
+    Sdr(RtlSdr),
+    File(File),
+}
+
+impl Reader {
+    fn open(samples: &Samples, sample_rate: u32) -> anyhow::Result<Self> {
+        Ok(match samples {
+            Samples::Sdr => Reader::Sdr(open(sample_rate)?),
+            Samples::File(path, _) => Reader::File(File::open(path)?),
+        })
+    }
+
+    /// Read whole samples into `buf`, returning the number of bytes read. Only files end, with 0
+    fn read(&mut self, buf: &mut [u8], bytes_per_sample: usize) -> anyhow::Result<usize> {
+        match self {
+            Reader::Sdr(sdr) => Ok(sdr.read_sync(buf)?),
+            Reader::File(file) => {
+                let mut n = 0;
+                while n < buf.len() {
+                    match file.read(&mut buf[n..])? {
+                        0 => break,
+                        read => n += read,
+                    }
+                }
+                Ok(n - n % bytes_per_sample)
+            }
+        }
+    }
+}
+
+/// Raw IQ samples read from the dongle, starting with the tail of the previous read so frames straddling two reads
+/// are demodulated too
+struct RawBlock {
+    seq: u64,
+    /// Index of the first sample in `iq`, counting from the start of reception
+    first_sample: u64,
+    iq: Vec<u8>,
+    /// Number of new samples, excluding the tail of the previous read
+    samples: u64,
+    /// Removes the DC offset measured by the reader, which sees every read
+    conversion: Conversion,
+    dropped_buffers: u64,
+    dropped_samples: u64,
+}
+
+/// The frames demodulated from a single read, in the order they were received
+pub struct DemodulatedBlock {
+    seq: u64,
+    /// Every frame with a valid preamble, which still needs to be validated
+    pub frames: Vec<Frame>,
+    /// Mode A/C replies, if enabled
+    pub mode_ac: Vec<ModeAcReply>,
+    pub preambles: u64,
+    /// Noise floor of the samples, as a power relative to full scale
+    pub noise_floor: f32,
+    /// I and Q DC offsets removed from the samples, relative to full scale
+    pub dc_offset: [f32; 2],
+    /// Number of samples read
+    pub samples: u64,
+    /// Buffers dropped since the previous block because the demodulators were busy
+    pub dropped_buffers: u64,
+    pub dropped_samples: u64,
+}
+
+/// Demodulated blocks from the workers, put back in order
+pub struct Blocks {
+    rx: Receiver<DemodulatedBlock>,
+    pending: BTreeMap<u64, DemodulatedBlock>,
+    next: u64,
+}
+
+impl Blocks {
+    /// The next block, if it arrives within `timeout`. Disconnected once every sample has been demodulated
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<DemodulatedBlock, RecvTimeoutError> {
+        loop {
+            if let Some(block) = self.pending.remove(&self.next) {
+                self.next += 1;
+                return Ok(block);
+            }
+
+            let block = self.rx.recv_timeout(timeout)?;
+            self.pending.insert(block.seq, block);
+        }
+    }
+}
+
+/// Everything a demodulation thread needs besides its queues
+#[derive(Clone)]
+pub struct Worker {
+    pub demodulator: Demodulator,
+    /// Looks for Mode A/C replies too, if given
+    pub mode_ac: Option<ModeAcDemodulator>,
+    /// Addresses of the tracked aircraft, to recognize their address/parity frames
+    pub known: KnownAddresses,
+    pub source: SourceId,
+}
+
+/// Open the samples and start reading and demodulating them on `workers` threads
+pub fn start(worker: Worker, workers: usize, samples: Samples) -> anyhow::Result<Blocks> {
+    let format = samples.format();
+    let (raw_tx, raw_rx) = mpsc::sync_channel(QUEUE_LENGTH);
+    let (free_tx, free_rx) = mpsc::channel();
+    let (demodulated_tx, demodulated_rx) = mpsc::sync_channel(QUEUE_LENGTH);
+
+    let raw_rx = Arc::new(Mutex::new(raw_rx));
+    for n in 0..workers.max(1) {
+        let raw_rx = raw_rx.clone();
+        let free_tx = free_tx.clone();
+        let demodulated_tx = demodulated_tx.clone();
+        let worker = worker.clone();
+        thread::Builder::new()
+            .name(format!("Demodulator {n}"))
+            .spawn(move || worker.run(&raw_rx, &free_tx, &demodulated_tx))?;
+    }
+
+    // The device is opened on the reader thread, only the outcome comes back
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let sample_rate = worker.demodulator.sample_rate();
+    let overlap = worker.demodulator.window() - 1;
+    thread::Builder::new()
+        .name("SDR reader".to_string())
+        .spawn(move || match Reader::open(&samples, sample_rate) {
+            Ok(mut reader) => {
+                let _ = ready_tx.send(Ok(()));
+                read(&mut reader, format, overlap, &raw_tx, &free_rx);
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+            }
+        })?;
+    ready_rx.recv()??;
+
+    Ok(Blocks {
+        rx: demodulated_rx,
+        pending: BTreeMap::new(),
+        next: 0,
+    })
+}
+
+fn open(sample_rate: u32) -> anyhow::Result<RtlSdr> {
+    // Open device
+    let mut sdr = RtlSdr::open(DEVICE_INDEX)?;
+    // info!("{:#?}", sdr);
+
+    let gains = sdr.get_tuner_gains()?;
+    info!(
+        "Supported gain values ({}): {:?}",
+        gains.len(),
+        gains,
+        // gains
+        //     .iter()
+        //     .map(|g| { *g as f32 / 10.0 })
+        //     .collect::<Vec<_>>()
+    );
+
+    // sdr.set_direct_sampling(rtlsdr_rs::DirectSampleMode::On)?;
+    // Set sample rate
+    sdr.set_sample_rate(sample_rate)?;
+    info!("Sampling at {} S/s", sdr.get_sample_rate());
+
+    // sdr.set_tuner_bandwidth(3_000_000)?;
+    sdr.set_bias_tee(false)?;
+    // sdr.set_tuner_gain(TunerGain::Manual(328))?;
+    sdr.set_tuner_gain(TunerGain::Manual(496))?;
+    // sdr.set_tuner_gain(TunerGain::Auto)?;
+
+    // sdr.set_center_freq(1_030_000_000)?; // Set center frequency to 1.090 GHz
+    sdr.set_freq_correction(0)?;
+    sdr.set_center_freq(1_090_000_000)?; // Set center frequency to 1.090 GHz
+
+    // Reset the endpoint before we try to read from it (mandatory)
+    info!("Reset buffer");
+    sdr.reset_buffer()?;
+
+    Ok(sdr)
+}
+
+/// Read buffers until the end of the file or the demodulators go away, keeping `overlap` samples of each read for the
+/// next. The DC offset is tracked here, over every read, and sent along with each
+fn read(
+    reader: &mut Reader,
+    format: IqFormat,
+    overlap: usize,
+    tx: &SyncSender<RawBlock>,
+    free: &Receiver<Vec<u8>>,
+) {
+    info!("Reading samples in sync mode...");
+    let bytes_per_sample = format.bytes_per_sample();
+    let mut tail: Vec<u8> = Vec::with_capacity(overlap * bytes_per_sample);
+    let mut spare = None;
+    let mut next_sample = 0u64;
+    let mut seq = 0;
+    let (mut dropped_buffers, mut dropped_samples) = (0, 0);
+    let mut converter = MagnitudeConverter::new(format);
+
+    loop {
+        let mut iq = spare
+            .take()
+            .or_else(|| free.try_recv().ok())
+            .unwrap_or_else(|| Vec::with_capacity(tail.len() + DEFAULT_BUF_LENGTH));
+        iq.clear();
+        iq.extend_from_slice(&tail);
+        let start = iq.len();
+        iq.resize(start + DEFAULT_BUF_LENGTH, 0);
+
+        let n = match reader.read(&mut iq[start..], bytes_per_sample) {
+            Ok(0) if matches!(reader, Reader::File(_)) => {
+                info!("End of the IQ file");
+                return;
+            }
+            Ok(n) => n,
+            Err(e) if matches!(reader, Reader::File(_)) => {
+                error!("Read error: {e:#}");
+                return;
+            }
+            Err(e) => {
+                error!("Read error: {e:#}");
+                spare = Some(iq);
+                continue;
+            }
+        };
+        iq.truncate(start + n);
+        tail.clear();
+        tail.extend_from_slice(&iq[iq.len().saturating_sub(overlap * bytes_per_sample)..]);
+
+        let block = RawBlock {
+            seq,
+            first_sample: next_sample - (start / bytes_per_sample) as u64,
+            samples: (n / bytes_per_sample) as u64,
+            conversion: converter.update(&iq[start..]),
+            iq,
+            dropped_buffers,
+            dropped_samples,
+        };
+        next_sample += block.samples;
+
+        // Files can wait for the demodulators
+        if let Reader::File(_) = reader {
+            if tx.send(block).is_err() {
+                return;
+            }
+            seq += 1;
+            continue;
+        }
+
+        match tx.try_send(block) {
+            Ok(()) => {
+                seq += 1;
+                (dropped_buffers, dropped_samples) = (0, 0);
+            }
+            Err(TrySendError::Full(block)) => {
+                if dropped_buffers == 0 {
+                    warn!("Demodulators are falling behind, dropping samples");
+                }
+                dropped_buffers += 1;
+                dropped_samples += block.samples;
+                spare = Some(block.iq);
+            }
+            Err(TrySendError::Disconnected(_)) => return,
+        }
+    }
+}
+
+impl Worker {
+    /// Demodulate blocks until the reader or the decoder goes away
+    fn run(
+        &self,
+        rx: &Mutex<Receiver<RawBlock>>,
+        free: &Sender<Vec<u8>>,
+        tx: &SyncSender<DemodulatedBlock>,
+    ) {
+        let window = self.demodulator.window();
+        let mut samples: Vec<u16> = Vec::with_capacity(DEFAULT_BUF_LENGTH / 2 + window);
+
+        loop {
+            let Ok(block) = rx.lock().unwrap().recv() else {
+                return;
+            };
+
+            samples.clear();
+            block.conversion.convert(&block.iq, &mut samples);
+            let _ = free.send(block.iq);
+            let noise_floor = magnitude::noise_floor(&samples);
+            let (frames, replies, preambles) =
+                self.demodulate(&samples, block.first_sample, noise_floor);
+
+            let demodulated = DemodulatedBlock {
+                seq: block.seq,
+                frames,
+                mode_ac: replies,
+                preambles,
+                noise_floor,
+                dc_offset: block.conversion.dc_offset,
+                samples: block.samples,
+                dropped_buffers: block.dropped_buffers,
+                dropped_samples: block.dropped_samples,
+            };
+            if tx.send(demodulated).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Demodulate the magnitudes of a block, whose first sample is `first_sample` since the start of reception.
+    ///
+    /// Returns the frames, the Mode A/C replies and the number of preambles found. Frames starting in the last window
+    /// of samples are left for the next block
+    fn demodulate(
+        &self,
+        samples: &[u16],
+        first_sample: u64,
+        noise_floor: f32,
+    ) -> (Vec<Frame>, Vec<ModeAcReply>, u64) {
+        let demodulator = &self.demodulator;
+        let window = demodulator.window();
+        let mut frames = Vec::new();
+        let mut replies = Vec::new();
+        let mut preambles = 0;
+        let mut offset = 0;
+        while offset + window <= samples.len() {
+            let now =
+                || Timestamp::from_samples(first_sample + offset as u64, demodulator.sample_rate());
+            let Some(demodulated) = demodulator.demodulate(&samples[offset..offset + window])
+            else {
+                // Mode A/C replies are only looked for where there is no Mode S preamble
+                match self.mode_ac.as_ref().and_then(|mode_ac| {
+                    let reply = mode_ac.demodulate(&samples[offset..offset + window], now())?;
+                    Some((reply, mode_ac.reply_length()))
+                }) {
+                    Some((reply, length)) => {
+                        replies.push(reply);
+                        offset += length;
+                    }
+                    None => offset += 1,
+                }
+                continue;
+            };
+
+            preambles += 1;
+            let preamble = demodulated.preamble;
+            debug!(
+                "Preamble at {offset}: signal {:.3}, noise {:.3} ({:.1} dB)",
+                preamble.signal,
+                preamble.noise,
+                preamble.snr_db()
+            );
+
+            let mut frame = Frame::new(
+                &demodulated.data,
+                now() + demodulated.delay,
+                demodulated.signal_level,
+            );
+            frame.source = self.source;
+            frame.confidence = Some(demodulated.confidence);
+            frame.noise_floor = Some(noise_floor);
+
+            // Skip past valid frames, so they aren't demodulated again at the next offsets. The address/parity of
+            // surveillance replies is valid if it recovers the address of a tracked aircraft
+            let valid = if decoder::has_address_parity(frame.downlink_format()) {
+                frame
+                    .icao()
+                    .is_some_and(|icao| self.known.read().unwrap().contains(&icao))
+            } else {
+                frame.fix_parity()
+            };
+            offset += if valid {
+                demodulator.frame_length(frame.bits())
+            } else {
+                1
+            };
+            frames.push(frame);
+        }
+
+        (frames, replies, preambles)
+    }
+}
+
+#[test]
+fn test_address_parity_frame_once() {
+    use crate::{aircraft::Icao, crc::mode_s_checksum, demod::frame_magnitudes};
+
+    // DF5 identity reply (squawk 1200) from 4840D6
+    let icao = Icao::new(0x4840D6);
+    let mut data = [0x28, 0x00, 0x08, 0x08, 0, 0, 0];
+    let parity = mode_s_checksum(&data, 56) ^ 0x4840D6;
+    data[4..].copy_from_slice(&parity.to_be_bytes()[1..]);
+
+    // 20 µs into the block, above a noise floor
+    let sample_rate = 2_400_000;
+    let demodulator = Demodulator::new(sample_rate, 6.0);
+    let mut m = frame_magnitudes(&data, sample_rate, 20.0);
+    m.resize(demodulator.window() * 2, 0);
+    let m = m.into_iter().map(|m| m + 100).collect::<Vec<_>>();
+
+    let worker = Worker {
+        demodulator,
+        mode_ac: None,
+        known: KnownAddresses::default(),
+        source: SourceId(0),
+    };
+    let copies = |worker: &Worker| {
+        let (frames, _, _) = worker.demodulate(&m, 0, 0.0);
+        assert!(frames.iter().any(|frame| frame.data() == data));
+        frames.len()
+    };
+
+    // Unknown addresses can't be told apart from noise, so the frame is demodulated again at the next offsets
+    assert!(copies(&worker) > 1);
+    worker.known.write().unwrap().insert(icao);
+    assert_eq!(copies(&worker), 1);
+}
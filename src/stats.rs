@@ -0,0 +1,103 @@
+use serde::Serialize;
+
+use crate::frame::SourceId;
+
+/// Weight of each new block of samples in the tracked noise floor
+const NOISE_SMOOTHING: f32 = 0.1;
+
+/// Receiver-wide counters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    /// Number of samples processed
+    pub samples: u64,
+    /// SDR buffers dropped because the demodulators couldn't keep up
+    pub buffers_dropped: u64,
+    /// Number of samples in those buffers
+    pub samples_dropped: u64,
+    /// Noise floor of the SDR samples, tracked continuously, in dBFS
+    pub noise_floor_dbfs: Option<f64>,
+    /// Mean signal strength of the valid frames demodulated from the SDR, in dBFS
+    pub signal_dbfs: Option<f64>,
+    /// Strongest of those frames, in dBFS
+    pub peak_signal_dbfs: Option<f64>,
+    /// I and Q DC offsets removed from the SDR samples, relative to full scale
+    pub dc_offset: Option<[f32; 2]>,
+    #[serde(skip)]
+    noise_power: Option<f32>,
+    #[serde(skip)]
+    signal_power: f64,
+    #[serde(skip)]
+    signal_frames: u64,
+    /// Number of windows that looked like a preamble
+    pub preambles: u64,
+    /// Mode A/C replies, if their demodulation is enabled
+    pub mode_ac: u64,
+    /// Mode A/C replies whose code is the squawk of exactly one tracked aircraft
+    pub mode_ac_matched: u64,
+    /// Frames that passed validation and were decoded
+    pub messages: u64,
+    /// Valid frames that needed error correction
+    pub messages_corrected: u64,
+    /// Frames with a bad CRC that could not be corrected
+    pub bad_crc: u64,
+    /// Surveillance replies from aircraft we don't know, which can't be validated
+    pub unverified: u64,
+    /// Copies of a frame that was already received shortly before, by any source
+    pub duplicates: u64,
+    pub tracks_new: u64,
+    pub tracks_lost: u64,
+    /// Counters per receiver, indexed by [`SourceId`]
+    pub sources: Vec<SourceStats>,
+}
+
+/// Counters for a single receiver
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SourceStats {
+    pub name: String,
+    pub messages: u64,
+    pub bad_crc: u64,
+    pub unverified: u64,
+    /// Frames that another source (or this one) had already delivered
+    pub duplicates: u64,
+}
+
+impl Stats {
+    pub fn add_source(&mut self, name: impl Into<String>) -> SourceId {
+        self.sources.push(SourceStats {
+            name: name.into(),
+            ..Default::default()
+        });
+        SourceId(self.sources.len() as u16 - 1)
+    }
+
+    pub fn source_mut(&mut self, source: SourceId) -> &mut SourceStats {
+        &mut self.sources[source.0 as usize]
+    }
+
+    /// Track the noise floor, given that of the latest block of samples as a power relative to full scale
+    pub fn add_noise_floor(&mut self, power: f32) {
+        let noise_power = match self.noise_power {
+            Some(noise) => noise + (power - noise) * NOISE_SMOOTHING,
+            None => power,
+        };
+        self.noise_power = Some(noise_power);
+        self.noise_floor_dbfs = Some(dbfs(noise_power as f64));
+    }
+
+    /// Count the signal level (power relative to full scale) of a valid frame demodulated here
+    pub fn add_signal(&mut self, power: f32) {
+        self.signal_power += power as f64;
+        self.signal_frames += 1;
+        self.signal_dbfs = Some(dbfs(self.signal_power / self.signal_frames as f64));
+        let signal_dbfs = dbfs(power as f64);
+        self.peak_signal_dbfs = Some(
+            self.peak_signal_dbfs
+                .map_or(signal_dbfs, |peak| peak.max(signal_dbfs)),
+        );
+    }
+}
+
+/// A power relative to full scale in dB, with the same floor as the aircraft RSSI
+fn dbfs(power: f64) -> f64 {
+    10.0 * (power + 1e-5).log10()
+}
@@ -0,0 +1,154 @@
+use std::{
+    fmt::Display,
+    ops::Add,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A point in time on the receiver's sample clock, in ticks of a 12 MHz clock (the same unit as Beast MLAT timestamps).
+///
+/// Deriving time from the sample counter instead of the wall clock keeps decoding independent of processing speed,
+/// so replays produce the same results as live reception.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const TICKS_PER_SECOND: u64 = 12_000_000;
+
+    /// Convert a sample index at the given sample rate to a timestamp
+    pub fn from_samples(samples: u64, sample_rate: u32) -> Self {
+        Timestamp((samples as u128 * Self::TICKS_PER_SECOND as u128 / sample_rate as u128) as u64)
+    }
+
+    pub fn from_ticks(ticks: u64) -> Self {
+        Timestamp(ticks)
+    }
+
+    pub fn ticks(self) -> u64 {
+        self.0
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.0 as f64 / Self::TICKS_PER_SECOND as f64
+    }
+
+    /// Time elapsed since `earlier`, or zero if `earlier` is later than this timestamp
+    pub fn saturating_duration_since(self, earlier: Timestamp) -> Duration {
+        let ticks = self.0.saturating_sub(earlier.0);
+        Duration::from_nanos(
+            (ticks as u128 * 1_000_000_000 / Self::TICKS_PER_SECOND as u128) as u64,
+        )
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        Timestamp(self.0 + (rhs.as_nanos() * Self::TICKS_PER_SECOND as u128 / 1_000_000_000) as u64)
+    }
+}
+
+/// Counts samples as they are read, and converts sample positions to timestamps
+pub struct SampleClock {
+    sample_rate: u32,
+    /// Number of samples before the current buffer
+    samples: u64,
+    /// Wall clock time at sample 0, if known
+    anchor: Option<SystemTime>,
+}
+
+impl SampleClock {
+    pub fn new(sample_rate: u32, anchor: Option<SystemTime>) -> Self {
+        SampleClock {
+            sample_rate,
+            samples: 0,
+            anchor,
+        }
+    }
+
+    /// Timestamp of the sample at `offset` into the current buffer
+    pub fn timestamp_at(&self, offset: usize) -> Timestamp {
+        Timestamp::from_samples(self.samples + offset as u64, self.sample_rate)
+    }
+
+    /// Drop `samples` from the front of the current buffer
+    pub fn advance(&mut self, samples: usize) {
+        self.samples += samples as u64;
+    }
+
+    pub fn wall_clock(&self, timestamp: Timestamp) -> Option<SystemTime> {
+        self.anchor
+            .map(|anchor| anchor + timestamp.saturating_duration_since(Timestamp::default()))
+    }
+
+    /// Convert a wall clock time to a timestamp, for frames that didn't come from the sample stream
+    pub fn timestamp_of(&self, time: SystemTime) -> Option<Timestamp> {
+        let anchor = self.anchor?;
+        Some(Timestamp::default() + time.duration_since(anchor).unwrap_or_default())
+    }
+}
+
+/// A wall clock time broken down into its UTC calendar date and time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtcDateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millisecond: u32,
+}
+
+impl From<SystemTime> for UtcDateTime {
+    fn from(time: SystemTime) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let days = (since_epoch.as_secs() / 86400) as i64;
+        let seconds_of_day = (since_epoch.as_secs() % 86400) as u32;
+
+        // Civil date from days since the epoch (http://howardhinnant.github.io/date_algorithms.html)
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+
+        UtcDateTime {
+            year,
+            month: month as u32,
+            day: day as u32,
+            hour: seconds_of_day / 3600,
+            minute: seconds_of_day / 60 % 60,
+            second: seconds_of_day % 60,
+            millisecond: since_epoch.subsec_millis(),
+        }
+    }
+}
+
+/// ISO 8601, eg. `2008-11-28T23:48:18.611Z`
+impl Display for UtcDateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.millisecond
+        )
+    }
+}
+
+#[test]
+fn test_timestamp_from_samples() {
+    assert_eq!(
+        Timestamp::from_samples(2_000_000, 2_000_000).as_secs_f64(),
+        1.0
+    );
+    assert_eq!(
+        Timestamp::from_samples(3_600_000, 2_400_000)
+            .saturating_duration_since(Timestamp::from_samples(1_200_000, 2_400_000)),
+        Duration::from_secs(1)
+    );
+}
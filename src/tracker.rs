@@ -0,0 +1,175 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use crate::{
+    aircraft::{Aircraft, Icao},
+    frame::{Frame, SourceId},
+    timestamp::Timestamp,
+};
+
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    /// Positions older than this are dropped and no longer interpolated
+    pub position_timeout: Duration,
+    /// Velocities older than this are dropped
+    pub velocity_timeout: Duration,
+    /// Aircraft that haven't sent any message for this long are removed entirely
+    pub target_timeout: Duration,
+    /// Latitude and longitude of the receiver, to decode surface positions of aircraft without a known position
+    pub receiver: Option<(f64, f64)>,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            position_timeout: Duration::from_secs(60),
+            velocity_timeout: Duration::from_secs(30),
+            target_timeout: Duration::from_secs(300),
+            receiver: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TrackerEvent {
+    /// First message received from an aircraft we weren't tracking yet
+    NewTarget(Icao),
+    /// The aircraft timed out and was removed from the tracker
+    LostTarget(Box<Aircraft>),
+}
+
+/// The addresses of the tracked aircraft, shared with the demodulators to recognize their address/parity frames
+pub type KnownAddresses = Arc<RwLock<HashSet<Icao>>>;
+
+/// Keeps track of all aircraft currently in range, and expires the ones that aren't
+pub struct Tracker {
+    pub config: TrackerConfig,
+    pub aircrafts: HashMap<Icao, Aircraft>,
+    known: KnownAddresses,
+    events: Vec<TrackerEvent>,
+}
+
+impl Tracker {
+    pub fn new(config: TrackerConfig) -> Self {
+        Tracker {
+            config,
+            aircrafts: HashMap::new(),
+            known: KnownAddresses::default(),
+            events: Vec::new(),
+        }
+    }
+
+    /// The addresses of the tracked aircraft, kept up to date as aircraft come and go
+    pub fn known_addresses(&self) -> KnownAddresses {
+        self.known.clone()
+    }
+
+    /// Get the aircraft with the given address, starting to track it if we haven't seen it before.
+    ///
+    /// Only use this for messages where the address is known to be correct (eg. CRC checked)
+    pub fn aircraft_seen(&mut self, icao: Icao, frame: &Frame) -> &mut Aircraft {
+        let craft = self.aircraft_entry(icao, frame.timestamp);
+        craft.record_frame(frame);
+        craft
+    }
+
+    /// Get the aircraft with the given address for state that was decoded elsewhere (eg. SBS input), starting to
+    /// track it if we haven't seen it before
+    pub fn aircraft_reported(
+        &mut self,
+        icao: Icao,
+        source: SourceId,
+        now: Timestamp,
+    ) -> &mut Aircraft {
+        let craft = self.aircraft_entry(icao, now);
+        craft.last_seen = now;
+        craft.seen_by.insert(source, now);
+        craft.messages.total += 1;
+        craft
+    }
+
+    fn aircraft_entry(&mut self, icao: Icao, now: Timestamp) -> &mut Aircraft {
+        self.aircrafts.entry(icao).or_insert_with(|| {
+            self.events.push(TrackerEvent::NewTarget(icao));
+            self.known.write().unwrap().insert(icao);
+            Aircraft::new(icao, now)
+        })
+    }
+
+    /// Get an aircraft we are already tracking, refreshing its last seen time
+    pub fn known_aircraft_seen(&mut self, icao: &Icao, frame: &Frame) -> Option<&mut Aircraft> {
+        let craft = self.aircrafts.get_mut(icao)?;
+        craft.record_frame(frame);
+        Some(craft)
+    }
+
+    /// Drop stale state and remove aircraft that have timed out
+    pub fn expire(&mut self, now: Timestamp) {
+        let config = &self.config;
+        for craft in self.aircrafts.values_mut() {
+            if craft
+                .position
+                .is_some_and(|p| now.saturating_duration_since(p.updated) > config.position_timeout)
+            {
+                craft.position = None;
+            }
+
+            if craft
+                .velocity_kts
+                .is_some_and(|v| now.saturating_duration_since(v.updated) > config.velocity_timeout)
+            {
+                craft.velocity_kts = None;
+            }
+        }
+
+        let expired = self
+            .aircrafts
+            .iter()
+            .filter(|(_, craft)| {
+                now.saturating_duration_since(craft.last_seen) > config.target_timeout
+            })
+            .map(|(icao, _)| *icao)
+            .collect::<Vec<_>>();
+
+        for icao in expired {
+            if let Some(craft) = self.aircrafts.remove(&icao) {
+                self.known.write().unwrap().remove(&icao);
+                self.events.push(TrackerEvent::LostTarget(Box::new(craft)));
+            }
+        }
+    }
+
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, TrackerEvent> {
+        self.events.drain(..)
+    }
+}
+
+#[test]
+fn test_tracker_expiry() {
+    let mut tracker = Tracker::new(TrackerConfig::default());
+    let start = Timestamp::default();
+
+    let frame = Frame::new(&[0x8D, 0x48, 0x40, 0xD6], start, 0.1);
+    tracker.aircraft_seen(Icao::new(0x4840D6), &frame);
+    tracker.aircraft_seen(Icao::new(0x4840D6), &frame);
+    let known = tracker.known_addresses();
+    assert!(known.read().unwrap().contains(&Icao::new(0x4840D6)));
+    assert!(matches!(
+        tracker.drain_events().collect::<Vec<_>>()[..],
+        [TrackerEvent::NewTarget(_)]
+    ));
+
+    tracker.expire(start + Duration::from_secs(10));
+    assert_eq!(tracker.aircrafts.len(), 1);
+
+    tracker.expire(start + tracker.config.target_timeout + Duration::from_secs(1));
+    assert!(tracker.aircrafts.is_empty());
+    assert!(known.read().unwrap().is_empty());
+    assert!(matches!(
+        tracker.drain_events().collect::<Vec<_>>()[..],
+        [TrackerEvent::LostTarget(_)]
+    ));
+}